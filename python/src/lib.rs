@@ -0,0 +1,342 @@
+//! # Python bindings of MerKurio.
+//!
+//! The `merkurio` Python module exposes pattern sets, matching on byte strings
+//! and iterators over the hits in FASTA/FASTQ and SAM/BAM files. Statistics are
+//! returned as dictionaries with the same fields as the JSON log. The module is
+//! built as a wheel with `maturin build --release` in this directory.
+
+use bam::RecordReader;
+use merkurio::{
+    MultiPatternMatcher, PatternSet, PatternStats, QualityFilter, RecordFilter, RecordSearch, SearchStats, json_record_id,
+};
+use needletail::parser::FastxReader;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+fn value_error(e: impl Display) -> PyErr {
+    PyValueError::new_err(format!("{e:#}"))
+}
+
+fn io_error(e: impl Display) -> PyErr {
+    PyIOError::new_err(e.to_string())
+}
+
+/// Converts a JSON value to the equivalent Python object.
+fn json_to_py<'py>(py: Python<'py>, value: &serde_json::Value) -> PyResult<Bound<'py, PyAny>> {
+    py.import("json")?.call_method1("loads", (value.to_string(),))
+}
+
+/// A sorted list of unique patterns, read from a file (FASTA or one k-mer per
+/// line) or given as sequences, preprocessed like by the subcommands.
+#[pyclass(name = "PatternSet", module = "merkurio", frozen)]
+struct PyPatternSet {
+    inner: PatternSet,
+}
+
+#[pymethods]
+impl PyPatternSet {
+    #[new]
+    #[pyo3(signature = (sequences=None, *, file=None, reverse_complement=false, canonical=false, lowercase=false, uppercase=false))]
+    fn new(
+        sequences: Option<Vec<String>>,
+        file: Option<PathBuf>,
+        reverse_complement: bool,
+        canonical: bool,
+        lowercase: bool,
+        uppercase: bool,
+    ) -> PyResult<Self> {
+        let mut builder = PatternSet::builder()
+            .reverse_complement(reverse_complement)
+            .canonical(canonical)
+            .lowercase(lowercase)
+            .uppercase(uppercase);
+        if let Some(sequences) = sequences {
+            builder = builder.sequences(sequences);
+        }
+        if let Some(file) = file {
+            builder = builder.file(file);
+        }
+        Ok(Self {
+            inner: builder.build().map_err(value_error)?,
+        })
+    }
+
+    /// The patterns; the index of a pattern is its id in the hits.
+    #[getter]
+    fn patterns(&self) -> Vec<String> {
+        self.inner.patterns().to_vec()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("PatternSet({} patterns)", self.inner.len())
+    }
+
+    /// Builds a matcher for the patterns. Without options, the algorithm is
+    /// chosen like on the command line; `q` forces BNDMq with that q-gram
+    /// length, and case-insensitive search always uses Aho-Corasick.
+    #[pyo3(signature = (*, aho_corasick=None, case_insensitive=false, q=None))]
+    fn matcher(&self, aho_corasick: Option<bool>, case_insensitive: bool, q: Option<usize>) -> PyResult<PyMatcher> {
+        let matcher = match aho_corasick {
+            None if !case_insensitive && q.is_none() => self.inner.matcher(),
+            _ => self.inner.matcher_with(aho_corasick.unwrap_or(false), case_insensitive, q),
+        };
+        Ok(PyMatcher {
+            patterns: self.inner.patterns().to_vec(),
+            matcher: matcher.map_err(value_error)?,
+        })
+    }
+}
+
+/// Searches byte strings for all patterns of a pattern set at once.
+#[pyclass(name = "Matcher", module = "merkurio", frozen)]
+struct PyMatcher {
+    patterns: Vec<String>,
+    matcher: Box<dyn MultiPatternMatcher>,
+}
+
+#[pymethods]
+impl PyMatcher {
+    #[getter]
+    fn patterns(&self) -> Vec<String> {
+        self.patterns.clone()
+    }
+
+    /// All (possibly overlapping) occurrences as `(pattern_id, start, end)`,
+    /// sorted by position and pattern id.
+    fn find_all(&self, text: &[u8]) -> Vec<(usize, usize, usize)> {
+        let mut occurrences: Vec<_> = self.matcher.find_iter(text).collect();
+        occurrences.sort_unstable_by_key(|&(id, start, _)| (start, id));
+        occurrences
+    }
+
+    /// Returns true if any of the patterns occurs in the text.
+    fn find_any(&self, text: &[u8]) -> bool {
+        self.matcher.find_any(text)
+    }
+
+    /// Returns the number of occurrences of all patterns in the text.
+    fn count(&self, text: &[u8]) -> usize {
+        self.matcher.count(text)
+    }
+}
+
+/// Searches the records of a file and collects the statistics of the hits.
+struct HitSearch {
+    matcher: Py<PyMatcher>,
+    file: String,
+    qual_offset: u8,
+    quality_filter: QualityFilter,
+    record_filter: RecordFilter,
+    stats: SearchStats,
+    hits: Vec<(usize, usize)>,
+    // Hits of the last record not yet returned, as (record ID, pattern index, position)
+    pending: VecDeque<(Vec<u8>, usize, usize)>,
+}
+
+impl HitSearch {
+    fn search(&mut self, id: &[u8], seq: &[u8], qual: Option<&[u8]>) {
+        let search = RecordSearch {
+            matcher: self.matcher.get().matcher.as_ref(),
+            quality_filter: self.quality_filter,
+            record_filter: self.record_filter,
+            qual_offset: self.qual_offset,
+        };
+        search.search(&mut self.stats, seq, qual, None, &mut self.hits);
+        self.pending
+            .extend(self.hits.iter().map(|&(pos, pattern_idx)| (id.to_vec(), pattern_idx, pos)));
+    }
+
+    /// A hit with the fields of the hits in the JSON log.
+    fn hit_dict<'py>(&self, py: Python<'py>, id: &[u8], pattern_idx: usize, pos: usize) -> PyResult<Bound<'py, PyDict>> {
+        let (record_id, record_id_hex) = json_record_id(id);
+        let hit = PyDict::new(py);
+        hit.set_item("file", &self.file)?;
+        hit.set_item("pattern", &self.matcher.get().patterns[pattern_idx])?;
+        hit.set_item("position", pos)?;
+        hit.set_item("record_id", record_id)?;
+        if let Some(hex) = record_id_hex {
+            hit.set_item("record_id_hex", hex)?;
+        }
+        Ok(hit)
+    }
+
+    fn summary_statistics(&self) -> serde_json::Value {
+        self.stats.summary_json(
+            &self.matcher.get().patterns,
+            self.quality_filter.is_active(),
+            self.record_filter.is_active(),
+            false,
+        )
+    }
+}
+
+enum RecordSource {
+    Fastx(Box<dyn FastxReader>),
+    Bam(Box<dyn RecordReader>, bam::Record),
+}
+
+/// Iterator over the hits in the records of a file, in record order and by
+/// position within a record. Each hit is a dictionary with the fields of the
+/// hits in the JSON log. The statistics cover the records read so far.
+#[pyclass(name = "HitIterator", module = "merkurio", unsendable)]
+struct HitIterator {
+    source: RecordSource,
+    search: HitSearch,
+}
+
+impl HitIterator {
+    /// Searches the next record. Returns false at the end of the file.
+    fn read_record(&mut self) -> PyResult<bool> {
+        match &mut self.source {
+            RecordSource::Fastx(reader) => match reader.next() {
+                Some(record) => {
+                    let record = record.map_err(io_error)?;
+                    self.search.search(record.id(), &record.seq(), record.qual());
+                    Ok(true)
+                }
+                None => Ok(false),
+            },
+            RecordSource::Bam(reader, record) => {
+                if !reader.read_into(record).map_err(io_error)? {
+                    return Ok(false);
+                }
+                let seq = record.sequence().to_vec();
+                // Missing qualities are stored as 0xFF
+                let qual = Some(record.qualities().raw()).filter(|q| q.first().is_some_and(|&q| q != 0xFF));
+                self.search.search(record.name(), &seq, qual);
+                Ok(true)
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl HitIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(&mut self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        loop {
+            if let Some((id, pattern_idx, pos)) = self.search.pending.pop_front() {
+                return self.search.hit_dict(py, &id, pattern_idx, pos).map(Some);
+            }
+            if !self.read_record()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// The `summary_statistics` section of the JSON log, with the
+    /// `pattern_statistics`.
+    fn statistics<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        json_to_py(py, &self.search.summary_statistics())
+    }
+
+    /// The `pattern_hit_counts` section of the JSON log.
+    fn pattern_hit_counts<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        json_to_py(py, &self.search.stats.patterns.occurrences_json(&self.search.matcher.get().patterns))
+    }
+}
+
+fn hit_search(
+    path: &Path,
+    matcher: Py<PyMatcher>,
+    qual_offset: u8,
+    quality_filter: QualityFilter,
+    record_filter: RecordFilter,
+) -> HitSearch {
+    let nb_patterns = matcher.get().patterns.len();
+    HitSearch {
+        matcher,
+        file: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        qual_offset,
+        quality_filter,
+        record_filter,
+        stats: SearchStats::new(PatternStats::new(nb_patterns)),
+        hits: Vec::new(),
+        pending: VecDeque::new(),
+    }
+}
+
+/// Iterates over the hits in a FASTA/FASTQ file (optionally compressed). The
+/// quality scores are decoded with `qual_offset`, 33 by default as in the
+/// subcommand extract (Sanger/Illumina 1.8+ FASTQ).
+#[pyfunction]
+#[pyo3(signature = (path, matcher, *, qual_offset=33, min_base_qual=None, min_mean_qual=None, min_length=None, max_length=None, max_n_fraction=None))]
+#[allow(clippy::too_many_arguments)]
+fn fastx_hits(
+    path: PathBuf,
+    matcher: Py<PyMatcher>,
+    qual_offset: u8,
+    min_base_qual: Option<u8>,
+    min_mean_qual: Option<f64>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    max_n_fraction: Option<f64>,
+) -> PyResult<HitIterator> {
+    let reader = needletail::parse_fastx_file(&path).map_err(io_error)?;
+    Ok(HitIterator {
+        source: RecordSource::Fastx(reader),
+        search: hit_search(
+            &path,
+            matcher,
+            qual_offset,
+            QualityFilter { min_base_qual, min_mean_qual },
+            RecordFilter { min_length, max_length, max_n_fraction },
+        ),
+    })
+}
+
+/// Iterates over the hits in a SAM or BAM file, by file extension. The
+/// quality scores of the records are decoded with `qual_offset`, 0 by default
+/// as they are stored without offset.
+#[pyfunction]
+#[pyo3(signature = (path, matcher, *, qual_offset=0, min_base_qual=None, min_mean_qual=None, min_length=None, max_length=None, max_n_fraction=None))]
+#[allow(clippy::too_many_arguments)]
+fn bam_hits(
+    path: PathBuf,
+    matcher: Py<PyMatcher>,
+    qual_offset: u8,
+    min_base_qual: Option<u8>,
+    min_mean_qual: Option<f64>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    max_n_fraction: Option<f64>,
+) -> PyResult<HitIterator> {
+    let reader: Box<dyn RecordReader> = match path.extension().and_then(|e| e.to_str()) {
+        Some("bam") => Box::new(bam::BamReader::from_path(&path, 0).map_err(io_error)?),
+        Some("sam") => Box::new(bam::SamReader::from_path(&path).map_err(io_error)?),
+        _ => return Err(value_error("Input file must be a BAM or SAM file.")),
+    };
+    Ok(HitIterator {
+        source: RecordSource::Bam(reader, bam::Record::new()),
+        search: hit_search(
+            &path,
+            matcher,
+            qual_offset,
+            QualityFilter { min_base_qual, min_mean_qual },
+            RecordFilter { min_length, max_length, max_n_fraction },
+        ),
+    })
+}
+
+#[pymodule]
+#[pyo3(name = "merkurio")]
+fn merkurio_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_class::<PyPatternSet>()?;
+    m.add_class::<PyMatcher>()?;
+    m.add_class::<HitIterator>()?;
+    m.add_function(wrap_pyfunction!(fastx_hits, m)?)?;
+    m.add_function(wrap_pyfunction!(bam_hits, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,101 @@
+"""Tests of the Python bindings, run with `python -m unittest` after building
+the module with `maturin develop`."""
+
+import unittest
+from pathlib import Path
+
+import merkurio
+
+INPUT = Path(__file__).resolve().parents[2] / "tests" / "fixtures" / "input"
+
+
+class TestPatternSet(unittest.TestCase):
+    def test_preprocessing(self):
+        patterns = merkurio.PatternSet(["ACG", "acg"], reverse_complement=True, uppercase=True)
+        self.assertEqual(patterns.patterns, ["ACG", "CGT"])
+        self.assertEqual(len(patterns), 2)
+
+    def test_invalid_pattern_sets(self):
+        with self.assertRaises(ValueError):
+            merkurio.PatternSet([""])
+        with self.assertRaises(ValueError):
+            merkurio.PatternSet(["ACG"], reverse_complement=True, canonical=True)
+
+
+class TestMatcher(unittest.TestCase):
+    def test_same_hits_for_all_algorithms(self):
+        patterns = merkurio.PatternSet(["ACG", "CGT"])
+        for matcher in [
+            patterns.matcher(),
+            patterns.matcher(aho_corasick=True),
+            patterns.matcher(q=1),
+        ]:
+            self.assertEqual(matcher.find_all(b"ACGTACGT"), [(0, 0, 3), (1, 1, 4), (0, 4, 7), (1, 5, 8)])
+            self.assertEqual(matcher.count(b"ACGTACGT"), 4)
+            self.assertTrue(matcher.find_any(b"TACGT"))
+            self.assertFalse(matcher.find_any(b"TTTT"))
+
+    def test_case_insensitive(self):
+        matcher = merkurio.PatternSet(["ACG"]).matcher(case_insensitive=True)
+        self.assertEqual(matcher.find_all(b"tacgt"), [(0, 1, 4)])
+
+
+class TestHitIterators(unittest.TestCase):
+    def test_fastx_hits(self):
+        matcher = merkurio.PatternSet(["ACG", "CGT"]).matcher()
+        hits = merkurio.fastx_hits(INPUT / "simple.fasta", matcher)
+        self.assertEqual(
+            [(hit["record_id"], hit["pattern"], hit["position"]) for hit in hits],
+            [
+                ("seq1", "ACG", 0),
+                ("seq1", "CGT", 1),
+                ("seq1", "ACG", 4),
+                ("seq1", "CGT", 5),
+                ("seq3", "ACG", 2),
+                ("seq3", "CGT", 3),
+            ],
+        )
+        stats = hits.statistics()
+        self.assertEqual(stats["number_of_records_searched"], 3)
+        self.assertEqual(stats["number_of_matches"], 6)
+        self.assertEqual(stats["number_of_distinct_records_with_a_hit"], 2)
+        self.assertIsNone(stats["number_of_hits_rejected_for_quality"])
+        self.assertEqual(stats["pattern_statistics"]["ACG"]["records"], 2)
+        self.assertEqual(hits.pattern_hit_counts(), {"ACG": 3, "CGT": 3})
+
+    def test_fastx_hits_quality_filter(self):
+        matcher = merkurio.PatternSet(["CTT"]).matcher()
+        hits = merkurio.fastx_hits(INPUT / "low-quality.fastq", matcher, min_base_qual=20)
+        self.assertEqual([(hit["record_id"], hit["position"]) for hit in hits], [("read2", 2)])
+        self.assertEqual(hits.statistics()["number_of_hits_rejected_for_quality"], 1)
+
+        # With the offset of Illumina 1.3+ FASTQ files, 'I' is a quality of 9
+        hits = merkurio.fastx_hits(INPUT / "low-quality.fastq", matcher, qual_offset=64, min_base_qual=5)
+        self.assertEqual([(hit["record_id"], hit["position"]) for hit in hits], [("read2", 2)])
+        hits = merkurio.fastx_hits(INPUT / "low-quality.fastq", matcher, qual_offset=64, min_base_qual=20)
+        self.assertEqual(list(hits), [])
+        self.assertEqual(hits.statistics()["number_of_hits_rejected_for_quality"], 2)
+
+    def test_bam_hits(self):
+        matcher = merkurio.PatternSet(["CGATCG"]).matcher()
+        for file in ["simple.sam", "simple.bam"]:
+            hits = merkurio.bam_hits(INPUT / file, matcher)
+            self.assertEqual([hit["position"] for hit in hits], [14, 2, 6, 10, 14])
+            self.assertEqual(hits.statistics()["number_of_distinct_records_with_a_hit"], 2)
+
+        hits = merkurio.bam_hits(INPUT / "simple.sam", matcher, min_length=100)
+        self.assertEqual(list(hits), [])
+        stats = hits.statistics()
+        self.assertEqual(stats["number_of_records_searched"], 0)
+        self.assertGreater(stats["number_of_records_skipped_by_filters"], 0)
+
+    def test_invalid_files(self):
+        matcher = merkurio.PatternSet(["ACG"]).matcher()
+        with self.assertRaises(ValueError):
+            merkurio.bam_hits(INPUT / "simple.fasta", matcher)
+        with self.assertRaises(OSError):
+            merkurio.fastx_hits(INPUT / "missing.fasta", matcher)
+
+
+if __name__ == "__main__":
+    unittest.main()
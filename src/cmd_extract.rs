@@ -10,24 +10,612 @@
 //! manually set the size of the _q_-grams. If the number of patterns is high or
 //! the patterns are long, the Aho-Corasick algorithm is used.
 
-use aho_corasick::AhoCorasick;
 use anyhow::{Context, Result};
-use clap::{ArgAction, ArgGroup, Args, crate_name, crate_version};
+use clap::{ArgAction, ArgGroup, Args, ValueEnum, crate_name, crate_version};
 use jiff::{Unit, Zoned};
-use serde_json;
+use needletail::FastxReader;
+use needletail::parser::{LineEnding, SequenceRecord, write_fasta, write_fastq};
 
 use std::collections::HashMap;
+use std::ffi::OsString;
 use std::{fs, env};
-use std::io::{self, BufWriter};
+use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::string::String;
 
+use crate::config::{ConfigValues, config_header, config_json, parse_subcommand_args};
 use crate::helpers::{
-    add_suffix_to_file_prefix, check_log_flag_conflict, identify_uncompressed_type,
-    parse_pattern_list, recommend_aho_corasick, error_if_directory,
+    QualityFilter, RecordFilter, SampleEntry, add_suffix_to_file_prefix, check_log_flag_conflict, error_if_directory,
+    identify_uncompressed_type, parse_pattern_list, read_kmer_groups_from_file, read_name_base,
+    parse_pattern_list_with_origins, read_sample_sheet, recommend_aho_corasick, sample_name_from_path, validate_sample_name,
 };
-use crate::logger::{BufferedLogger, JsonLogger};
-use crate::pattern_matching::{BNDMq, tune_q_value};
+use crate::hit_table::{HitLocation, HitTable};
+use crate::logger::{BufferedLogger, FeatureFormat, FeatureLogger, JsonFormat, JsonLogger, LogLevel, summary_json};
+use crate::pattern_matching::build_matcher;
+use crate::stats::{PatternStats, RecordSearch, SearchStats};
+
+/// Policy deciding which hits select a read pair in paired-end mode.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PairMode {
+    /// A hit in either read selects the pair.
+    Either,
+    /// Both reads must contain a hit.
+    Both,
+    /// Only the first read is searched.
+    R1,
+    /// Only the second read is searched.
+    R2,
+}
+
+impl PairMode {
+    /// Returns true if a pair with the given hits in read 1 and read 2 is selected.
+    pub fn selects(&self, hit_1: bool, hit_2: bool) -> bool {
+        match self {
+            PairMode::Either => hit_1 || hit_2,
+            PairMode::Both => hit_1 && hit_2,
+            PairMode::R1 => hit_1,
+            PairMode::R2 => hit_2,
+        }
+    }
+
+    /// Returns true if the second read has to be searched to decide whether
+    /// the pair is selected, given the result for the first read.
+    pub fn needs_read_2(&self, hit_1: bool) -> bool {
+        match self {
+            PairMode::Either => !hit_1,
+            PairMode::Both => hit_1,
+            PairMode::R1 => false,
+            PairMode::R2 => true,
+        }
+    }
+
+    /// Name of the pair mode as used on the command line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PairMode::Either => "either",
+            PairMode::Both => "both",
+            PairMode::R1 => "r1",
+            PairMode::R2 => "r2",
+        }
+    }
+}
+
+/// How to handle read pairs whose names do not match in paired-end mode.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PairNameCheck {
+    /// Do not compare read names.
+    Off,
+    /// Warn about mismatching read names and count them in the summary.
+    Lenient,
+    /// Stop with an error at the first mismatching read names.
+    Strict,
+}
+
+impl PairNameCheck {
+    /// Name of the check mode as used on the command line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PairNameCheck::Off => "off",
+            PairNameCheck::Lenient => "lenient",
+            PairNameCheck::Strict => "strict",
+        }
+    }
+}
+
+/// Unit of the counts written to the count matrix.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CountUnit {
+    /// Number of records containing the pattern.
+    Records,
+    /// Number of occurrences of the pattern.
+    Occurrences,
+}
+
+/// Key for splitting extracted records into several output files.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitBy {
+    /// One output file per pattern.
+    Pattern,
+    /// One output file per pattern group (FASTA header in the k-mer file).
+    Group,
+}
+
+/// Where to write records that match several patterns or groups when the
+/// output is split.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MultiHit {
+    /// Write the record to the file of each matching pattern or group.
+    Duplicate,
+    /// Write the record only to the file of its first hit.
+    First,
+    /// Write the record to a separate 'multi' file.
+    Multi,
+}
+
+/// How matched k-mers are masked in extracted records.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskMode {
+    /// Convert the bases of hits to lowercase.
+    Soft,
+    /// Replace the bases of hits with 'N' and set their qualities to zero.
+    Hard,
+}
+
+/// Which part of extracted records is trimmed around matched k-mers.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrimMode {
+    /// Remove the bases from the start of the record to the end of the last hit.
+    Left,
+    /// Remove the bases from the start of the first hit to the end of the record.
+    Right,
+    /// Remove only the bases of the hits.
+    Hit,
+}
+
+/// Edit applied to the hits of extracted records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum HitEdit {
+    Mask(MaskMode),
+    Trim(TrimMode),
+}
+
+impl HitEdit {
+    /// Mask or trim the hit ranges `[start, end)` of a sequence, adjusting
+    /// the qualities accordingly.
+    fn apply(self, seq: &mut Vec<u8>, mut qual: Option<&mut Vec<u8>>, hits: &[(usize, usize)]) {
+        if hits.is_empty() {
+            return;
+        }
+        match self {
+            HitEdit::Mask(mode) => {
+                for &(start, end) in hits {
+                    match mode {
+                        MaskMode::Soft => seq[start..end].make_ascii_lowercase(),
+                        MaskMode::Hard => {
+                            seq[start..end].fill(b'N');
+                            if let Some(qual) = qual.as_mut() {
+                                qual[start..end].fill(FASTQ_QUAL_OFFSET);
+                            }
+                        }
+                    }
+                }
+            }
+            HitEdit::Trim(TrimMode::Left) => {
+                let end = hits.iter().map(|&(_, end)| end).max().unwrap_or(0);
+                seq.drain(..end);
+                if let Some(qual) = qual {
+                    qual.drain(..end);
+                }
+            }
+            HitEdit::Trim(TrimMode::Right) => {
+                let start = hits.iter().map(|&(start, _)| start).min().unwrap_or(seq.len());
+                seq.truncate(start);
+                if let Some(qual) = qual {
+                    qual.truncate(start);
+                }
+            }
+            HitEdit::Trim(TrimMode::Hit) => {
+                let mut keep = vec![true; seq.len()];
+                for &(start, end) in hits {
+                    keep[start..end].fill(false);
+                }
+                let mut keep_seq = keep.iter();
+                seq.retain(|_| *keep_seq.next().unwrap());
+                if let Some(qual) = qual {
+                    let mut keep_qual = keep.iter();
+                    qual.retain(|_| *keep_qual.next().unwrap());
+                }
+            }
+        }
+    }
+}
+
+/// Returns the ranges `[start, end)` of the hits of one mate.
+fn hit_ranges(record_hits: &[(usize, usize, usize)], mate: usize, pattern_list: &[String]) -> Vec<(usize, usize)> {
+    record_hits
+        .iter()
+        .filter(|&&(hit_mate, _, _)| hit_mate == mate)
+        .map(|&(_, pos, idx)| (pos, pos + pattern_list[idx].len()))
+        .collect()
+}
+
+/// Returns the windows of `flank` bases around the hits of a sequence of
+/// length `len`, with overlapping or adjacent windows merged. Each window is
+/// given as range `[start, end)` with the indices of the patterns found in it.
+fn flank_windows(
+    record_hits: &[(usize, usize, usize)],
+    pattern_list: &[String],
+    flank: usize,
+    len: usize,
+) -> Vec<(usize, usize, Vec<usize>)> {
+    let mut hits: Vec<(usize, usize, usize)> = record_hits
+        .iter()
+        .map(|&(_, pos, idx)| (pos, pos + pattern_list[idx].len(), idx))
+        .collect();
+    hits.sort_unstable();
+
+    let mut windows: Vec<(usize, usize, Vec<usize>)> = Vec::new();
+    for (start, end, idx) in hits {
+        let start = start.saturating_sub(flank);
+        let end = (end + flank).min(len);
+        match windows.last_mut() {
+            Some(window) if start <= window.1 => {
+                window.1 = window.1.max(end);
+                window.2.push(idx);
+            }
+            _ => windows.push((start, end, vec![idx])),
+        }
+    }
+    for window in &mut windows {
+        window.2.sort_unstable();
+        window.2.dedup();
+    }
+    windows
+}
+
+/// Write the windows around the hits of a record as separate records. The
+/// headers give the name of the source record, the one-based coordinates of
+/// the window and the k-mers found in it, e.g. `>contig1:101-160 kmers=ACGT`.
+fn write_flank_windows(
+    record: &SequenceRecord,
+    record_hits: &[(usize, usize, usize)],
+    pattern_list: &[String],
+    flank: usize,
+    writer: &mut dyn io::Write,
+) -> Result<()> {
+    let seq = record.seq();
+    let name = record
+        .id()
+        .split(|b| b.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default();
+    for (start, end, patterns) in flank_windows(record_hits, pattern_list, flank, seq.len()) {
+        let kmers: Vec<&str> = patterns.iter().map(|&idx| pattern_list[idx].as_str()).collect();
+        let mut id = name.to_vec();
+        id.extend_from_slice(format!(":{}-{} kmers={}", start + 1, end, kmers.join(",")).as_bytes());
+        match record.qual() {
+            Some(qual) => write_fastq(&id, &seq[start..end], Some(&qual[start..end]), writer, record.line_ending()),
+            None => write_fasta(&id, &seq[start..end], writer, record.line_ending()),
+        }
+        .with_context(|| "Error writing window to output file.")?;
+    }
+    Ok(())
+}
+
+/// BED and GFF outputs of the hit locations, with the name and strand of
+/// each pattern (also used for the strand column of the hit table).
+struct HitFeatures {
+    loggers: Vec<FeatureLogger>,
+    pattern_features: Vec<(String, char)>,
+}
+
+impl HitFeatures {
+    /// Open the BED and GFF outputs and name the patterns after their FASTA
+    /// header in the k-mer file, or after the k-mer they were derived from.
+    /// Reverse complements added with `-r` (or canonical forms that differ
+    /// from the k-mer) are reported on the minus strand.
+    fn new(args: &CmdExtract, pattern_list: &[String]) -> Result<Self> {
+        let mut loggers = Vec::new();
+        for (path, format) in [(&args.bed_out, FeatureFormat::Bed), (&args.gff_out, FeatureFormat::Gff)] {
+            if let Some(path) = path {
+                loggers.push(FeatureLogger::new(create_file_writer(path)?, format, 8192)?);
+            }
+        }
+
+        let named_kmers = match (&args.kmer_file, &args.kmer_seq) {
+            (Some(kmer_file), _) => read_kmer_groups_from_file(kmer_file)?,
+            (None, Some(kmer_seq)) => kmer_seq.iter().map(|k| (k.clone(), k.clone())).collect(),
+            (None, None) => Vec::new(),
+        };
+        let mut features: HashMap<String, (String, char)> = HashMap::new();
+        for (name, kmer) in named_kmers {
+            // Apply the same preprocessing as for the pattern list
+            let forward = parse_pattern_list(&None, Some(vec![kmer.clone()]), false, false, args.lowercase, args.uppercase)?;
+            let forms = parse_pattern_list(
+                &None,
+                Some(vec![kmer]),
+                args.reverse_complement,
+                args.canonical,
+                args.lowercase,
+                args.uppercase,
+            )?;
+            for form in forms {
+                let strand = if forward.contains(&form) { '+' } else { '-' };
+                features.entry(form).or_insert_with(|| (name.clone(), strand));
+            }
+        }
+        let pattern_features = pattern_list
+            .iter()
+            .map(|pattern| features.get(pattern).cloned().unwrap_or_else(|| (pattern.clone(), '+')))
+            .collect();
+
+        Ok(Self { loggers, pattern_features })
+    }
+
+    /// Write a hit of the pattern with index `idx` at position `pos`.
+    fn log_hit(&mut self, record_id: &[u8], idx: usize, pattern: &str, pos: usize) -> io::Result<()> {
+        let (name, strand) = &self.pattern_features[idx];
+        for logger in &mut self.loggers {
+            logger.log_feature(record_id, name, pattern, pos, pos + pattern.len(), *strand)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for logger in &mut self.loggers {
+            logger.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of the output file suffix for records matching several patterns or
+/// groups with `--multi-hit multi`.
+const MULTI_LABEL: &str = "multi";
+
+/// Output labels of the patterns when splitting the extracted records.
+struct Demux {
+    labels: Vec<String>,
+    pattern_label: Vec<usize>,
+    multi_hit: MultiHit,
+}
+
+impl Demux {
+    /// Assign each pattern to its own label or to the group of the k-mer it
+    /// was derived from. Labels are made safe for use in file names.
+    fn new(args: &CmdExtract, pattern_list: &[String], split_by: SplitBy) -> Result<Self> {
+        let mut pattern_group: HashMap<String, String> = HashMap::new();
+        if let (SplitBy::Group, Some(kmer_file)) = (split_by, &args.kmer_file) {
+            for (group, kmer) in read_kmer_groups_from_file(kmer_file)? {
+                // Apply the same preprocessing as for the pattern list
+                let forms = parse_pattern_list(
+                    &None,
+                    Some(vec![kmer]),
+                    args.reverse_complement,
+                    args.canonical,
+                    args.lowercase,
+                    args.uppercase,
+                )?;
+                for form in forms {
+                    pattern_group.entry(form).or_insert_with(|| group.clone());
+                }
+            }
+        }
+
+        let mut labels: Vec<String> = Vec::new();
+        let mut pattern_label = Vec::with_capacity(pattern_list.len());
+        for pattern in pattern_list {
+            let label = file_label(pattern_group.get(pattern).unwrap_or(pattern));
+            if args.multi_hit == MultiHit::Multi && label == MULTI_LABEL {
+                anyhow::bail!(
+                    "The output label '{MULTI_LABEL}' is reserved for records matching several patterns or groups."
+                );
+            }
+            let idx = match labels.iter().position(|l| *l == label) {
+                Some(idx) => idx,
+                None => {
+                    labels.push(label);
+                    labels.len() - 1
+                }
+            };
+            pattern_label.push(idx);
+        }
+
+        Ok(Self {
+            labels,
+            pattern_label,
+            multi_hit: args.multi_hit,
+        })
+    }
+
+    /// Labels of the files a record is written to, given its hits as
+    /// (read of the pair, position, pattern index). The 'multi' bucket has
+    /// the index after the last label.
+    fn targets(&self, hits: &[(usize, usize, usize)]) -> Vec<usize> {
+        if self.multi_hit == MultiHit::First {
+            return hits
+                .iter()
+                .min()
+                .map(|&(_, _, pattern_idx)| vec![self.pattern_label[pattern_idx]])
+                .unwrap_or_default();
+        }
+        let mut labels: Vec<usize> = hits
+            .iter()
+            .map(|&(_, _, pattern_idx)| self.pattern_label[pattern_idx])
+            .collect();
+        labels.sort_unstable();
+        labels.dedup();
+        if self.multi_hit == MultiHit::Multi && labels.len() > 1 {
+            return vec![self.labels.len()];
+        }
+        labels
+    }
+
+    fn label_name(&self, idx: usize) -> &str {
+        self.labels.get(idx).map_or(MULTI_LABEL, String::as_str)
+    }
+}
+
+/// Replace characters that are not safe in file names with underscores.
+fn file_label(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Writers for the first and, unless interleaved, second reads of a label.
+type LabelWriters = (Box<dyn io::Write>, Option<Box<dyn io::Write>>);
+
+/// Output files of one sample when splitting the extracted records, opened
+/// when the first record of a label is written. Paired-end reads go to two
+/// files per label unless the output is interleaved.
+struct DemuxWriters {
+    out_path: PathBuf,
+    paired_files: bool,
+    writers: HashMap<usize, LabelWriters>,
+}
+
+impl DemuxWriters {
+    fn new(out_path: &Path, paired_files: bool) -> Self {
+        Self {
+            out_path: out_path.to_path_buf(),
+            paired_files,
+            writers: HashMap::new(),
+        }
+    }
+
+    /// Get the writers for a label, creating the output files if needed.
+    fn get(
+        &mut self,
+        demux: &Demux,
+        label: usize,
+    ) -> Result<&mut LabelWriters> {
+        if !self.writers.contains_key(&label) {
+            let path = add_suffix_to_file_prefix(
+                &self.out_path,
+                &format!("_{}", demux.label_name(label)),
+            );
+            let writers = if self.paired_files {
+                (
+                    create_file_writer(&add_suffix_to_file_prefix(&path, "_1"))?,
+                    Some(create_file_writer(&add_suffix_to_file_prefix(&path, "_2"))?),
+                )
+            } else {
+                (create_file_writer(&path)?, None)
+            };
+            self.writers.insert(label, writers);
+        }
+        Ok(self.writers.get_mut(&label).unwrap())
+    }
+
+    /// Flush the output files of all labels, reporting write errors.
+    fn flush_all(&mut self) -> Result<()> {
+        for (writer, writer2) in self.writers.values_mut() {
+            writer.flush()?;
+            if let Some(writer2) = writer2 {
+                writer2.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Number of read pairs per hit category in paired-end mode.
+#[derive(Debug, Default, Clone, Copy)]
+struct PairHitCounts {
+    both: usize,
+    read_1_only: usize,
+    read_2_only: usize,
+    none: usize,
+}
+
+/// Owned copy of a FASTQ/A record. Both reads of a pair have to be kept in
+/// memory at the same time, which is not possible with records borrowed from
+/// a single reader (interleaved input). Buffers are reused between pairs.
+struct OwnedRecord {
+    id: Vec<u8>,
+    raw_seq: Vec<u8>,
+    seq: Vec<u8>,
+    qual: Option<Vec<u8>>,
+    line_ending: LineEnding,
+}
+
+impl OwnedRecord {
+    fn new() -> Self {
+        Self {
+            id: Vec::new(),
+            raw_seq: Vec::new(),
+            seq: Vec::new(),
+            qual: None,
+            line_ending: LineEnding::Unix,
+        }
+    }
+
+    /// Overwrite this record with the contents of a parsed record.
+    fn copy_from(&mut self, record: &SequenceRecord) {
+        self.id.clear();
+        self.id.extend_from_slice(record.id());
+        self.raw_seq.clear();
+        self.raw_seq.extend_from_slice(record.raw_seq());
+        self.seq.clear();
+        self.seq.extend_from_slice(&record.seq());
+        self.qual = record.qual().map(|q| {
+            let mut qual = self.qual.take().unwrap_or_default();
+            qual.clear();
+            qual.extend_from_slice(q);
+            qual
+        });
+        self.line_ending = record.line_ending();
+    }
+
+    /// Mask or trim the hits of the record. The edited sequence is written
+    /// on a single line.
+    fn edit_hits(&mut self, edit: HitEdit, hits: &[(usize, usize)]) {
+        edit.apply(&mut self.seq, self.qual.as_mut(), hits);
+        self.raw_seq.clone_from(&self.seq);
+    }
+
+    /// Write the record in its original format (FASTA or FASTQ).
+    fn write(&self, writer: &mut dyn io::Write) -> Result<()> {
+        match &self.qual {
+            Some(qual) => write_fastq(&self.id, &self.raw_seq, Some(qual), writer, self.line_ending),
+            None => write_fasta(&self.id, &self.raw_seq, writer, self.line_ending),
+        }
+        .with_context(|| "Error writing record to output file.")
+    }
+}
+
+/// Reader for pairs of records, either from two files or from a single
+/// interleaved file.
+enum PairReader {
+    Separate(Box<dyn FastxReader>, Box<dyn FastxReader>),
+    Interleaved(Box<dyn FastxReader>),
+}
+
+impl PairReader {
+    /// Read the next pair of records into `pair`. Returns false if the
+    /// input is exhausted.
+    fn read_pair(&mut self, pair: &mut [OwnedRecord; 2]) -> Result<bool> {
+        match self {
+            PairReader::Separate(reader_1, reader_2) => {
+                match reader_1.next() {
+                    Some(r) => pair[0].copy_from(
+                        &r.with_context(|| "Error during FASTQ record parsing of first file.")?,
+                    ),
+                    None => {
+                        if reader_2.next().is_some() {
+                            anyhow::bail!(
+                                "The two input files have a different number of records. Please provide valid paired-end read files."
+                            );
+                        }
+                        return Ok(false);
+                    }
+                }
+                let r = reader_2
+                    .next()
+                    .with_context(|| "Error during FASTQ record parsing of second file. Do the two input files contain the same number of records?")?;
+                pair[1].copy_from(
+                    &r.with_context(|| "Error during FASTQ record parsing of second file.")?,
+                );
+            }
+            PairReader::Interleaved(reader) => {
+                match reader.next() {
+                    Some(r) => pair[0].copy_from(
+                        &r.with_context(|| "Error during FASTQ record parsing of interleaved file.")?,
+                    ),
+                    None => return Ok(false),
+                }
+                let r = reader
+                    .next()
+                    .with_context(|| "The interleaved input file contains an odd number of records. Please provide a valid interleaved paired-end read file.")?;
+                pair[1].copy_from(
+                    &r.with_context(|| "Error during FASTQ record parsing of interleaved file.")?,
+                );
+            }
+        }
+        Ok(true)
+    }
+}
 
 #[derive(Args)]
 #[clap(group(
@@ -46,7 +634,19 @@ group(
     ArgGroup::new("logging")
         .required(false)
         .multiple(true)
-        .args(&["out_log", "json_log"]),
+        .args(&["out_log", "json_log", "count_matrix"]),
+),
+group(
+    ArgGroup::new("paired-input")
+        .required(false)
+        .multiple(false)
+        .args(&["in_fastq_2", "interleaved"]),
+),
+group(
+    ArgGroup::new("paired-source")
+        .required(false)
+        .multiple(true)
+        .args(&["in_fastq_2", "interleaved", "sample_sheet"]),
 ),
 group(
     ArgGroup::new("case-sensitivity")
@@ -62,13 +662,33 @@ group(
 ))]
 #[derive(Clone)]
 pub struct CmdExtract {
-    /// Input path for (compressed) FASTQ/A file.
-    #[clap(short = 'i', long, short_alias = '1')]
-    in_fastx: PathBuf,
+    /// Input path for (compressed) FASTQ/A file; several files can be given, each is processed as a separate sample and written to its own file in the output directory.
+    #[clap(short = 'i', long, short_alias = '1', num_args = 1.., required_unless_present("sample_sheet"))]
+    in_fastx: Vec<PathBuf>,
+
+    /// Input path for second FASTQ file (only for paired-end read processing); with several samples, in the same order as the first files.
+    #[clap(short = '2', long, num_args = 1..)]
+    in_fastq_2: Vec<PathBuf>,
+
+    /// Tab-separated sample sheet with the columns sample, R1 and optionally R2, used instead of -i and -2; each sample is written to its own file in the output directory.
+    #[clap(long, conflicts_with_all(["in_fastx", "in_fastq_2"]))]
+    sample_sheet: Option<PathBuf>,
 
-    /// Input path for second FASTQ file (only for paired-end read processing).
-    #[clap(short = '2', long, required = false)]
-    in_fastq_2: Option<PathBuf>,
+    /// Treat the input file as interleaved paired-end reads, where consecutive records form a pair; output is interleaved as well.
+    #[clap(long, action(ArgAction::SetTrue), default_value("false"))]
+    interleaved: bool,
+
+    /// Write paired-end reads from two input files to a single interleaved output file instead of two files with '_1' and '_2' suffixes.
+    #[clap(long, action(ArgAction::SetTrue), default_value("false"), requires("paired-source"), conflicts_with("interleaved"))]
+    interleaved_out: bool,
+
+    /// Which reads of a pair must contain a hit to select the pair (only for paired-end read processing).
+    #[clap(long, value_enum, default_value_t = PairMode::Either, requires("paired-source"))]
+    pair_mode: PairMode,
+
+    /// Check that both reads of a pair have the same name (ignoring '/1' and '/2' suffixes and comments); 'lenient' warns about mismatches, 'strict' stops with an error.
+    #[clap(long, value_enum, default_value_t = PairNameCheck::Off)]
+    pair_name_check: PairNameCheck,
 
     /// Query sequences (accepts multiple sequences after the flag, separated by a space); if not provided, input path for file containing list of k-mers is required.
     #[clap(short = 's', long, num_args = 1..)]
@@ -94,10 +714,86 @@ pub struct CmdExtract {
     #[clap(short = 'l', long, default_value(None), default_missing_value("STDOUT"), num_args = 0..=1, )]
     out_log: Option<PathBuf>,
 
+    /// Detail of the plain text log: one line per hit, one line per record with its hits, or only the summary statistics.
+    #[clap(long, value_enum, default_value_t = LogLevel::Hit, requires("out_log"))]
+    log_level: LogLevel,
+
     /// Write JSON log to stdout if only the flag is passed, or to a file if a path is provided.
     #[clap(short = 'j', long, default_value(None), default_missing_value("STDOUT"), num_args = 0..=1, )]
     json_log: Option<PathBuf>,
 
+    /// Layout of the JSON log: a single pretty-printed document, or JSON Lines with one hit per line and a final summary line.
+    #[clap(long, value_enum, default_value_t = JsonFormat::Pretty, requires("json_log"))]
+    json_format: JsonFormat,
+
+    /// Write a tab-separated matrix of pattern counts to a file, with a row for each pattern and a column for each sample.
+    #[clap(long)]
+    count_matrix: Option<PathBuf>,
+
+    /// Count records with a hit or all occurrences of a pattern in the count matrix.
+    #[clap(long, value_enum, default_value_t = CountUnit::Records, requires("count_matrix"))]
+    count_unit: CountUnit,
+
+    /// Normalise the count matrix to counts per million records searched in each sample.
+    #[clap(long, action(ArgAction::SetTrue), default_value("false"), requires("count_matrix"))]
+    normalize_counts: bool,
+
+    /// Split the extracted records into one output file per pattern or per pattern group (FASTA headers in the k-mer file); the pattern or group name is added to the output file names.
+    #[clap(long, value_enum, requires("out_fastx"), conflicts_with("invert_match"))]
+    split_by: Option<SplitBy>,
+
+    /// Where to write records matching several patterns or groups when splitting the output: to each file, to the file of the first hit, or to a separate 'multi' file.
+    #[clap(long, value_enum, default_value_t = MultiHit::Duplicate, requires("split_by"))]
+    multi_hit: MultiHit,
+
+    /// Write the records that are not extracted to this file (extension derived from input file); for paired-end reads, two files with '_1' and '_2' suffixes are written unless the output is interleaved.
+    #[clap(long, conflicts_with("suppress_output"))]
+    unmatched_out: Option<PathBuf>,
+
+    /// Mask the matched k-mers in the extracted records, either in lowercase (soft) or with 'N' and a quality of zero (hard).
+    #[clap(long, value_enum, conflicts_with_all(["trim", "invert_match"]))]
+    mask: Option<MaskMode>,
+
+    /// Trim the extracted records: remove everything up to the end of the last hit (left), from the start of the first hit (right), or only the hits themselves (hit); qualities are trimmed accordingly.
+    #[clap(long, value_enum, conflicts_with("invert_match"))]
+    trim: Option<TrimMode>,
+
+    /// Write only the subsequences from this many bases before to this many bases after each hit instead of the whole records, merging overlapping windows (single-end input only).
+    #[clap(long, conflicts_with_all(["invert_match", "mask", "trim"]))]
+    flank: Option<usize>,
+
+    /// Write the location of every hit to this BED6 file (record name, start, end, k-mer name, score, strand); k-mers are named after their FASTA header in the k-mer file, if any.
+    #[clap(long)]
+    bed_out: Option<PathBuf>,
+
+    /// Write the location of every hit to this GFF3 file, e.g. to load the hits in a genome browser.
+    #[clap(long)]
+    gff_out: Option<PathBuf>,
+
+    /// Write every hit to this Parquet file with typed columns (file, record ID, pattern, position, strand), e.g. to load the hits into Polars or DuckDB; the summary statistics are stored as file metadata.
+    #[clap(long)]
+    hits_parquet: Option<PathBuf>,
+
+    /// Only count hits whose bases all have at least this Phred quality score (ignored for FASTA input).
+    #[clap(long)]
+    min_base_qual: Option<u8>,
+
+    /// Only count hits whose bases have at least this mean Phred quality score (ignored for FASTA input).
+    #[clap(long)]
+    min_mean_qual: Option<f64>,
+
+    /// Skip reads shorter than this length (they are not searched and count as reads without a hit).
+    #[clap(long)]
+    min_length: Option<usize>,
+
+    /// Skip reads longer than this length (they are not searched and count as reads without a hit).
+    #[clap(long)]
+    max_length: Option<usize>,
+
+    /// Skip reads with a higher fraction of 'N' bases than this value between 0 and 1 (they are not searched and count as reads without a hit).
+    #[clap(long)]
+    max_n_fraction: Option<f64>,
+
     /// Suppress output of found records (no records are written to a file or stdout); use if only matching statistics are of interest.
     #[clap(
         short = 'S',
@@ -138,6 +834,156 @@ pub struct CmdExtract {
         hide_short_help = true
     )]
     aho_corasick: bool,
+
+    /// TOML configuration file with options for the subcommands; options given on the command line take precedence.
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Name of a preset in the configuration file to apply on top of the options for the subcommand.
+    #[clap(long, requires("config"))]
+    preset: Option<String>,
+
+    /// Options applied from the configuration file, set before running the subcommand.
+    #[clap(skip)]
+    pub config_values: ConfigValues,
+}
+
+impl CmdExtract {
+    /// Creates the options of the `extract` subcommand from its command-line
+    /// arguments, e.g. `CmdExtract::from_args(["-i", "reads.fq", "-s", "ACG", "-o", "out.fq"])`. A configuration file given
+    /// with `--config` is applied as on the command line.
+    pub fn from_args<I, A>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<OsString>,
+    {
+        let (mut cmd, config_values) = parse_subcommand_args::<Self, _, _>("extract", args)?;
+        cmd.config_values = config_values;
+        Ok(cmd)
+    }
+}
+
+/// Counters collected while searching the records of one sample.
+#[derive(Debug, Default, Clone)]
+struct SampleStats {
+    search: SearchStats,
+    // Hits and records with a hit in the first and second read file
+    nb_hits_in_file: [usize; 2],
+    nb_records_hit_in_file: [usize; 2],
+    nb_pairs_hit: PairHitCounts,
+    nb_pairs_name_mismatch: usize,
+    nb_records_extracted: usize,
+}
+
+impl SampleStats {
+    fn new(patterns: PatternStats) -> Self {
+        Self {
+            search: SearchStats::new(patterns),
+            ..Default::default()
+        }
+    }
+
+    /// Add the counters of another sample to this one.
+    fn add(&mut self, other: &SampleStats) {
+        self.search.merge(&other.search);
+        for mate in 0..2 {
+            self.nb_hits_in_file[mate] += other.nb_hits_in_file[mate];
+            self.nb_records_hit_in_file[mate] += other.nb_records_hit_in_file[mate];
+        }
+        self.nb_pairs_hit.both += other.nb_pairs_hit.both;
+        self.nb_pairs_hit.read_1_only += other.nb_pairs_hit.read_1_only;
+        self.nb_pairs_hit.read_2_only += other.nb_pairs_hit.read_2_only;
+        self.nb_pairs_hit.none += other.nb_pairs_hit.none;
+        self.nb_pairs_name_mismatch += other.nb_pairs_name_mismatch;
+        self.nb_records_extracted += other.nb_records_extracted;
+    }
+}
+
+/// Compiled pattern matchers and loggers, shared by all samples of a run.
+struct SearchContext<'a> {
+    pattern_list: &'a [String],
+    search: RecordSearch<'a>,
+    logging_active: bool,
+    logger: &'a mut BufferedLogger,
+    json_logger: &'a mut Option<JsonLogger>,
+    demux: Option<&'a Demux>,
+    hit_edit: Option<HitEdit>,
+    features: Option<HitFeatures>,
+    hit_table: Option<HitTable>,
+    // Empty statistics of the patterns, copied for each sample
+    pattern_stats: PatternStats,
+}
+
+impl SearchContext<'_> {
+    /// Log a hit, given as (position, pattern index), to all outputs.
+    fn report_hit(&mut self, file: &str, record_id: &[u8], record_nb: usize, hit: (usize, usize)) -> Result<()> {
+        let (pos, pattern_idx) = hit;
+        let pattern = &self.pattern_list[pattern_idx];
+        self.logger.log_hit(file, record_id, record_nb, pattern, pos)?;
+        if let Some(jl) = self.json_logger.as_mut() {
+            jl.log_fields(file, record_id, pattern, pos)?;
+        }
+        if let Some(features) = self.features.as_mut() {
+            features.log_hit(record_id, pattern_idx, pattern, pos)?;
+        }
+        if let Some(table) = self.hit_table.as_mut() {
+            let strand = self.features.as_ref().map(|f| f.pattern_features[pattern_idx].1);
+            table.log_hit(file, record_id, pattern, pos, HitLocation { strand, ..Default::default() })?;
+        }
+        Ok(())
+    }
+}
+
+/// Offset of the Phred quality scores in FASTQ files.
+const FASTQ_QUAL_OFFSET: u8 = 33;
+
+/// Collect the samples to process, either from the sample sheet or from the
+/// input files given on the command line.
+fn collect_samples(args: &CmdExtract) -> Result<Vec<SampleEntry>> {
+    let samples = match &args.sample_sheet {
+        Some(sheet) => read_sample_sheet(sheet)
+            .with_context(|| format!("Problem reading sample sheet: {}", sheet.display()))?,
+        None => {
+            if !args.in_fastq_2.is_empty() && args.in_fastq_2.len() != args.in_fastx.len() {
+                anyhow::bail!(
+                    "Got {} first and {} second read files; please provide the same number of files for -i and -2.",
+                    args.in_fastx.len(),
+                    args.in_fastq_2.len()
+                );
+            }
+            let samples: Vec<SampleEntry> = args
+                .in_fastx
+                .iter()
+                .enumerate()
+                .map(|(i, in_1)| SampleEntry {
+                    name: sample_name_from_path(in_1),
+                    in_1: in_1.clone(),
+                    in_2: args.in_fastq_2.get(i).cloned(),
+                })
+                .collect();
+            for (i, sample) in samples.iter().enumerate() {
+                if samples[..i].iter().any(|s| s.name == sample.name) {
+                    anyhow::bail!(
+                        "Sample name '{}' derived from '{}' is not unique; use a sample sheet to name the samples.",
+                        sample.name,
+                        sample.in_1.display()
+                    );
+                }
+            }
+            samples
+        }
+    };
+
+    if args.interleaved
+        && let Some(sample) = samples.iter().find(|s| s.in_2.is_some())
+    {
+        anyhow::bail!(
+            "Sample '{}' has a second read file, but the input is declared as interleaved.",
+            sample.name
+        );
+    }
+
+    Ok(samples)
 }
 
 pub fn extract_records(args: CmdExtract) -> Result<()> {
@@ -152,9 +998,9 @@ pub fn extract_records(args: CmdExtract) -> Result<()> {
 
     let mut args = args;
 
-    let pattern_list = parse_pattern_list(
+    let (pattern_list, pattern_origins) = parse_pattern_list_with_origins(
         &args.kmer_file,
-        args.kmer_seq,
+        args.kmer_seq.clone(),
         args.reverse_complement,
         args.canonical,
         args.lowercase,
@@ -170,6 +1016,32 @@ pub fn extract_records(args: CmdExtract) -> Result<()> {
         args.aho_corasick = recommend_aho_corasick(&pattern_list)?;
     }
 
+    // Several samples are written to separate files in an output directory
+    let samples = collect_samples(&args)?;
+    let batch = args.sample_sheet.is_some() || samples.len() > 1;
+    if batch {
+        match &args.out_fastx {
+            Some(out_dir) => fs::create_dir_all(out_dir).with_context(|| {
+                format!("Error creating output directory: {}", out_dir.display())
+            })?,
+            None if !args.suppress_output => anyhow::bail!(
+                "Processing several samples requires an output directory (-o) or suppressed output (-S)."
+            ),
+            None => {}
+        }
+        if let Some(unmatched_dir) = &args.unmatched_out {
+            fs::create_dir_all(unmatched_dir).with_context(|| {
+                format!("Error creating output directory: {}", unmatched_dir.display())
+            })?;
+        }
+    }
+    let paired = samples
+        .iter()
+        .any(|s| s.in_2.is_some() || args.interleaved);
+    if paired && args.flank.is_some() {
+        anyhow::bail!("Extracting windows around hits with --flank is only supported for single-end reads.");
+    }
+
     // Set one of thre possible logging options:
     // 1) log to stdout,
     // 2) log to file,
@@ -195,22 +1067,52 @@ pub fn extract_records(args: CmdExtract) -> Result<()> {
     // -------------------- Initialization & Preprocessing --------------------
     //
 
-    // Check if file paths point to directories and gets the file names
-    error_if_directory(&args.in_fastx, "Record file path")?;
-    let in_fastx_filename = args.in_fastx.file_name().unwrap().to_str().unwrap();
-    let in_fastq_2_filename = match &args.in_fastq_2 {
-        Some(p) => {
-            error_if_directory(p, "Second read file path")?; 
-            p.file_name().unwrap().to_str().unwrap()
-        },
-        None => "",
+    // Splitting the output by pattern or group needs all hits of a record
+    let demux = match args.split_by {
+        Some(split_by) => Some(
+            Demux::new(&args, &pattern_list, split_by)
+                .with_context(|| "Problem assigning patterns to output files.")?,
+        ),
+        None => None,
+    };
+
+    let hit_edit = match (args.mask, args.trim) {
+        (Some(mask), _) => Some(HitEdit::Mask(mask)),
+        (None, Some(trim)) => Some(HitEdit::Trim(trim)),
+        (None, None) => None,
+    };
+
+    // Hit locations for the BED and GFF outputs and the hit table
+    let features = if args.bed_out.is_some() || args.gff_out.is_some() || args.hits_parquet.is_some() {
+        Some(
+            HitFeatures::new(&args, &pattern_list)
+                .with_context(|| "Problem creating BED/GFF output.")?,
+        )
+    } else {
+        None
+    };
+    let hit_table = match &args.hits_parquet {
+        Some(path) => Some(
+            HitTable::create(path)
+                .with_context(|| format!("Problem creating hit table: {}", path.display()))?,
+        ),
+        None => None,
     };
 
-    // Activate logging if a log or JSON log file is provided
-    let logging_active = log_file.is_some() || args.json_log.is_some();
+    // Activate logging if a log or JSON log file, a count matrix, split
+    // output or the positions of all hits (masking, trimming, windows,
+    // BED/GFF output, hit table) are requested
+    let logging_active = log_file.is_some()
+        || args.json_log.is_some()
+        || args.count_matrix.is_some()
+        || demux.is_some()
+        || hit_edit.is_some()
+        || args.flank.is_some()
+        || features.is_some()
+        || hit_table.is_some();
 
     // Initialize buffered logger with 8KB buffer
-    let mut logger = BufferedLogger::new(log_file, 8192);
+    let mut logger = BufferedLogger::with_level(log_file, 8192, args.log_level);
 
     // Initialize JSON logger if requested
     let mut json_logger = if let Some(json_path) = args.json_log.clone() {
@@ -221,7 +1123,7 @@ pub fn extract_records(args: CmdExtract) -> Result<()> {
                 format!("Error creating JSON log file: {}", json_path.display())
             })?)
         };
-        Some(JsonLogger::new(Some(writer), 8192))
+        Some(JsonLogger::with_format(Some(writer), 8192, args.json_format)?)
     } else {
         None
     };
@@ -229,17 +1131,18 @@ pub fn extract_records(args: CmdExtract) -> Result<()> {
     // Log the list of patterns and header line
     if logging_active {
         // Write header section
-        logger.write_header("#SeqKatcher extract log\n");
-        logger.write_header(&format!("#{}\n", Zoned::now().round(Unit::Second)?));
+        logger.write_header("#SeqKatcher extract log\n")?;
+        logger.write_header(&format!("#{}\n", Zoned::now().round(Unit::Second)?))?;
         logger.write_header(&format!(
             "#Running {} version {}\n",
             crate_name!(),
             crate_version!()
-        ));
+        ))?;
         logger.write_header(&format!(
             "#Command line: {}\n",
             env::args().collect::<Vec<String>>().join(" ")
-        ));
+        ))?;
+        logger.write_header(&config_header(args.config.as_deref(), args.preset.as_deref(), &args.config_values))?;
         logger.write_header(&format!(
             "#Searching for {} pattern{} {}\n",
             pattern_list.len(),
@@ -249,188 +1152,541 @@ pub fn extract_records(args: CmdExtract) -> Result<()> {
             } else {
                 ""
             }
-        ));
-        logger.write_header("#\n#File\tRecord\tPattern\tPosition (zero-based)\n");
-        logger.flush(); // Ensure header is written before records
-    }
-
-    // Initialize algorithm instances for each pattern. Only construct the Aho-
-    // Corasick automaton when requested.
-    let (ac, bndmq_collection): (Option<AhoCorasick>, Vec<(String, BNDMq)>) = if args.aho_corasick {
-        let ac = AhoCorasick::builder()
-            // Use DFA for better search performance at higher memory cost
-            .kind(Some(aho_corasick::AhoCorasickKind::DFA))
-            .ascii_case_insensitive(args.case_insensitive)
-            .build(pattern_list.clone())
-            .unwrap();
-        (Some(ac), Vec::new())
-    } else {
-        let mut bndmq_collection = Vec::with_capacity(pattern_list.len());
-        for pattern in &pattern_list {
-            // Tune q value for BNDMq based on the pattern length if not provided
-            let q = args
-                .q_size
-                .unwrap_or_else(|| tune_q_value(pattern).unwrap());
-            bndmq_collection.push((pattern.clone(), BNDMq::new(pattern.as_bytes(), q)?));
-        }
-        (None, bndmq_collection)
-    };
-
-    // Uses a gzip decoder or regular file reader to read FASTQ/A records,
-    // depending on the file extension
-    let mut reader = needletail::parse_fastx_file(&args.in_fastx)
-        .with_context(|| format!("Invalid FASTQ/A input path or file: {:?}", &args.in_fastx))?;
+        ))?;
+        logger.write_column_header()?;
+        logger.flush()?; // Ensure header is written before records
+    }
 
-    // Initialize counters for logging information
-    let mut nb_records_tot = 0;
-    let mut nb_bases = 0;
-    let mut nb_hits_tot = (0, 0);
-    let mut nb_records_hit = (0, 0);
-    let mut nb_records_extracted = 0;
-    let mut pattern_hit_counts: Vec<u32> = vec![0; pattern_list.len()];
+    // Initialize the matcher once for all samples. The Aho-Corasick automaton
+    // is only constructed when requested.
+    let matcher = build_matcher(&pattern_list, args.aho_corasick, args.case_insensitive, args.q_size)?;
 
     //
     // ------------------ Pattern Matching & Output Writing -------------------
     //
 
-    //
-    // If no second file is provided, process single file
-    if args.in_fastq_2.is_none() {
-        // Either write to file or stdout if no output path is provided;
-        // the file format is determined by the input file
-        let mut writer = match &args.out_fastx {
-            Some(pathbuf) => {
-                let pathbuf =
-                    pathbuf.with_extension(identify_uncompressed_type(&args.in_fastx).unwrap());
-                let path = Path::new(&pathbuf);
-                let file = fs::File::create(path).with_context(|| {
-                    format!(
-                        "Error writing to output file; no such directory: {path:?}",
-                        
-                    )
-                })?;
-                Box::new(BufWriter::new(file)) as Box<dyn io::Write>
-            }
-            None => {
-                let stdout = io::stdout();
-                Box::new(BufWriter::new(stdout)) as Box<dyn io::Write>
-            }
+    let quality_filter = QualityFilter {
+        min_base_qual: args.min_base_qual,
+        min_mean_qual: args.min_mean_qual,
+    };
+    let record_filter = RecordFilter {
+        min_length: args.min_length,
+        max_length: args.max_length,
+        max_n_fraction: args.max_n_fraction,
+    };
+    // Count the hits of the k-mers per strand if their reverse complements
+    // are searched
+    let pattern_stats = if args.reverse_complement {
+        PatternStats::with_origins(&pattern_origins)
+    } else {
+        PatternStats::new(pattern_list.len())
+    };
+    let mut ctx = SearchContext {
+        pattern_list: &pattern_list,
+        search: RecordSearch {
+            matcher: matcher.as_ref(),
+            quality_filter,
+            record_filter,
+            qual_offset: FASTQ_QUAL_OFFSET,
+        },
+        logging_active,
+        logger: &mut logger,
+        json_logger: &mut json_logger,
+        demux: demux.as_ref(),
+        hit_edit,
+        features,
+        hit_table,
+        pattern_stats,
+    };
+    let mut sample_stats = Vec::with_capacity(samples.len());
+    for sample in &samples {
+        let out_path = match &args.out_fastx {
+            Some(out) => Some(sample_output_path(out, sample, batch)?),
+            None => None,
+        };
+        let unmatched_path = match &args.unmatched_out {
+            Some(out) => Some(sample_output_path(out, sample, batch)?),
+            None => None,
+        };
+        sample_stats.push(extract_sample(
+            &args,
+            sample,
+            out_path.as_deref(),
+            unmatched_path.as_deref(),
+            &mut ctx,
+        )?);
+    }
+    if let Some(features) = ctx.features.as_mut() {
+        features.flush()?;
+    }
+    let hit_table = ctx.hit_table.take();
+
+    let mut stats = SampleStats::new(PatternStats::new(pattern_list.len()));
+    for s in &sample_stats {
+        stats.add(s);
+    }
+    let SampleStats {
+        search,
+        nb_hits_in_file,
+        nb_records_hit_in_file,
+        nb_pairs_hit,
+        nb_pairs_name_mismatch,
+        nb_records_extracted,
+    } = stats;
+    let SearchStats {
+        nb_records_tot,
+        nb_bases,
+        nb_hits_tot,
+        nb_records_hit,
+        nb_hits_rejected,
+        nb_records_skipped,
+        ref patterns,
+    } = search;
+
+    // Log summary statistics as plain text and/or JSON
+    if logging_active {
+        logger.flush()?;
+        let nb_patterns_found = patterns.nb_patterns_found();
+        let nb_patterns_found_percentage =
+            nb_patterns_found as f64 / pattern_list.len() as f64 * 100.0;
+        logger.write_header(&format!(
+            "#\n#Number of patterns found: {}/{} ({:.2} %)\n",
+            nb_patterns_found,
+            pattern_list.len(),
+            nb_patterns_found_percentage,
+        ))?;
+        logger.write_header("#Pattern\tCount\n")?;
+        for (pattern, count) in pattern_list.iter().zip(patterns.occurrences.iter()) {
+            logger.write_header(&format!("#{pattern}\t{count}\n"))?;
+        }
+        logger.write_header(&format!(
+            "#\n#Total number of records searched: {nb_records_tot}\n"
+        ))?;
+        logger.write_header(&format!(
+            "#Total number of characters searched: {nb_bases}\n"
+        ))?;
+        logger.write_header(&format!(
+            "#Total number of hits: {nb_hits_tot}\n"
+        ))?;
+        logger.write_header(&format!(
+            "#Number of distinct records with a hit: {nb_records_hit}\n"
+        ))?;
+        if quality_filter.is_active() {
+            logger.write_header(&format!(
+                "#Number of hits rejected for low base quality: {nb_hits_rejected}\n"
+            ))?;
+        }
+        if record_filter.is_active() {
+            logger.write_header(&format!(
+                "#Number of records skipped by filters: {nb_records_skipped}\n"
+            ))?;
+        }
+        if paired {
+            logger.write_header(&format!(
+                "#\n#Total number of hits in file 1: {}\n",
+                nb_hits_in_file[0]
+            ))?;
+            logger.write_header(&format!(
+                "#Total number of hits in file 2: {}\n",
+                nb_hits_in_file[1]
+            ))?;
+            logger.write_header(&format!(
+                "#Number of distinct records with a hit in file 1: {}\n",
+                nb_records_hit_in_file[0]
+            ))?;
+            logger.write_header(&format!(
+                "#Number of distinct records with a hit in file 2: {}\n",
+                nb_records_hit_in_file[1]
+            ))?;
+            logger.write_header(&format!(
+                "#Total number of extracted records: {nb_records_extracted}\n"
+            ))?;
+            logger.write_header(&format!(
+                "#\n#Pair mode: {}\n",
+                args.pair_mode.as_str()
+            ))?;
+            logger.write_header(&format!(
+                "#Number of pairs with a hit in both reads: {}\n",
+                nb_pairs_hit.both
+            ))?;
+            logger.write_header(&format!(
+                "#Number of pairs with a hit in read 1 only: {}\n",
+                nb_pairs_hit.read_1_only
+            ))?;
+            logger.write_header(&format!(
+                "#Number of pairs with a hit in read 2 only: {}\n",
+                nb_pairs_hit.read_2_only
+            ))?;
+            logger.write_header(&format!(
+                "#Number of pairs without a hit: {}\n",
+                nb_pairs_hit.none
+            ))?;
+            if args.pair_name_check != PairNameCheck::Off {
+                logger.write_header(&format!(
+                    "#\n#Pair name check: {}\n",
+                    args.pair_name_check.as_str()
+                ))?;
+                logger.write_header(&format!(
+                    "#Number of pairs with mismatching read names: {nb_pairs_name_mismatch}\n"
+                ))?;
+            }
+        }
+        if batch {
+            logger.write_header(&format!(
+                "#\n#Number of samples: {}\n",
+                samples.len()
+            ))?;
+            logger.write_header(
+                "#Sample\tRecords searched\tCharacters searched\tHits\tRecords with a hit\tExtracted records\n",
+            )?;
+            for (sample, s) in samples.iter().zip(sample_stats.iter()) {
+                logger.write_header(&format!(
+                    "#{}\t{}\t{}\t{}\t{}\t{}\n",
+                    sample.name,
+                    s.search.nb_records_tot,
+                    s.search.nb_bases,
+                    s.search.nb_hits_tot,
+                    s.search.nb_records_hit,
+                    s.nb_records_extracted
+                ))?;
+            }
+        }
+        logger.write_header(&patterns.to_text(&pattern_list, paired))?;
+        logger.flush()?;
+    }
+
+    if let Some(path) = &args.count_matrix {
+        write_count_matrix(
+            path,
+            &pattern_list,
+            &samples,
+            &sample_stats,
+            args.count_unit,
+            args.normalize_counts,
+        )?;
+    }
+
+    // Finalize JSON log and hit table if active
+    if json_logger.is_some() || hit_table.is_some() {
+        let file_name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().to_string());
+        let single = samples.first().filter(|_| !batch);
+        let name_check = args.pair_name_check != PairNameCheck::Off;
+        let input_files_json = serde_json::json!({
+            "kmer_file": args.kmer_file.as_ref().map(|p| p.to_string_lossy().to_string()),
+            "record_file_1": single.and_then(|s| file_name(&s.in_1)),
+            "record_file_2": single.and_then(|s| s.in_2.as_deref().and_then(file_name)),
+            "interleaved": args.interleaved,
+            "sample_sheet": args.sample_sheet.as_ref().map(|p| p.to_string_lossy().to_string()),
+        });
+        let meta_information = serde_json::json!({
+            "program": crate_name!(),
+            "version": crate_version!(),
+            "timestamp": Zoned::now().round(Unit::Second).unwrap(),
+            "subcommand": "extract",
+            "command_line": env::args().collect::<Vec<String>>(),
+            "search_algorithm": if args.aho_corasick { "Aho-Corasick" } else { "BNDMq" },
+            "inverted_matching": args.invert_match,
+            "case_insensitive": args.case_insensitive,
+            "input_files": input_files_json,
+            "configuration": config_json(args.config.as_deref(), args.preset.as_deref(), &args.config_values),
+        });
+        let summary_statistics =
+            search.summary_json(&pattern_list, quality_filter.is_active(), record_filter.is_active(), paired);
+        let paired_end_stats = serde_json::json!({
+            "searching_paired_end_reads": paired,
+            "number_of_hits_in_file_1": nb_hits_in_file[0],
+            "number_of_hits_in_file_2": if paired { Some(nb_hits_in_file[1]) } else { None },
+            "number_of_distinct_records_with_a_hit_in_file_1": nb_records_hit_in_file[0],
+            "number_of_distinct_records_with_a_hit_in_file_2": if paired { Some(nb_records_hit_in_file[1]) } else { None },
+            "number_of_extracted_records": nb_records_extracted,
+            "pair_mode": if paired { Some(args.pair_mode.as_str()) } else { None },
+            "number_of_pairs_with_a_hit_in_both_reads": if paired { Some(nb_pairs_hit.both) } else { None },
+            "number_of_pairs_with_a_hit_in_read_1_only": if paired { Some(nb_pairs_hit.read_1_only) } else { None },
+            "number_of_pairs_with_a_hit_in_read_2_only": if paired { Some(nb_pairs_hit.read_2_only) } else { None },
+            "number_of_pairs_without_a_hit": if paired { Some(nb_pairs_hit.none) } else { None },
+            "pair_name_check": if paired { Some(args.pair_name_check.as_str()) } else { None },
+            "number_of_pairs_with_mismatching_read_names": if paired && name_check { Some(nb_pairs_name_mismatch) } else { None },
+        });
+        let per_sample_stats = serde_json::Value::Array(
+            samples
+                .iter()
+                .zip(sample_stats.iter())
+                .map(|(sample, s)| {
+                    let sample_paired = sample.in_2.is_some() || args.interleaved;
+                    serde_json::json!({
+                        "sample": sample.name,
+                        "record_file_1": sample.in_1.to_string_lossy(),
+                        "record_file_2": sample.in_2.as_ref().map(|p| p.to_string_lossy().to_string()),
+                        "searching_paired_end_reads": sample_paired,
+                        "number_of_patterns_found": s.search.patterns.nb_patterns_found(),
+                        "number_of_records_searched": s.search.nb_records_tot,
+                        "number_of_characters_searched": s.search.nb_bases,
+                        "number_of_matches": s.search.nb_hits_tot,
+                        "number_of_distinct_records_with_a_hit": s.search.nb_records_hit,
+                        "number_of_extracted_records": s.nb_records_extracted,
+                        "number_of_hits_rejected_for_quality": if quality_filter.is_active() { Some(s.search.nb_hits_rejected) } else { None },
+                        "number_of_records_skipped_by_filters": if record_filter.is_active() { Some(s.search.nb_records_skipped) } else { None },
+                        "number_of_pairs_with_mismatching_read_names": if sample_paired && name_check { Some(s.nb_pairs_name_mismatch) } else { None },
+                        "pattern_hit_counts": s.search.patterns.occurrences_json(&pattern_list),
+                    })
+                })
+                .collect(),
+        );
+        let per_sample_stats = if batch { Some(&per_sample_stats) } else { None };
+        if let Some(table) = hit_table {
+            table.finalize(&summary_json(
+                &meta_information,
+                &patterns.occurrences_json(&pattern_list),
+                &summary_statistics,
+                Some(&paired_end_stats),
+                per_sample_stats,
+            ))?;
+        }
+        if let Some(jl) = json_logger {
+            jl.finalize(
+                &meta_information,
+                &patterns.occurrences_json(&pattern_list),
+                &summary_statistics,
+                Some(&paired_end_stats),
+                per_sample_stats,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Output path of a sample: the given path with the extension of the input
+/// file, or a file named after the sample in the given output directory when
+/// several samples are processed.
+fn sample_output_path(out: &Path, sample: &SampleEntry, batch: bool) -> Result<PathBuf> {
+    // The file format of the output is determined by the input file
+    let ext = identify_uncompressed_type(&sample.in_1)?;
+    Ok(if batch {
+        validate_sample_name(&sample.name)?;
+        out.join(format!("{}.{ext}", sample.name))
+    } else {
+        out.with_extension(ext)
+    })
+}
+
+/// Create a buffered writer for an output file.
+fn create_file_writer(path: &Path) -> Result<Box<dyn io::Write>> {
+    let file = fs::File::create(path).with_context(|| {
+        format!("Error writing to output file; no such directory: {path:?}")
+    })?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+/// Write a tab-separated count matrix with a row for each pattern and a
+/// column for each sample. Normalised counts are given per million records
+/// searched in the sample.
+fn write_count_matrix(
+    path: &Path,
+    pattern_list: &[String],
+    samples: &[SampleEntry],
+    sample_stats: &[SampleStats],
+    unit: CountUnit,
+    normalize: bool,
+) -> Result<()> {
+    let file = fs::File::create(path)
+        .with_context(|| format!("Problem creating count matrix file: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+
+    let mut header = String::from("pattern");
+    for sample in samples {
+        header.push('\t');
+        header.push_str(&sample.name);
+    }
+    writeln!(writer, "{header}")?;
+
+    for (idx, pattern) in pattern_list.iter().enumerate() {
+        write!(writer, "{pattern}")?;
+        for stats in sample_stats {
+            let count = match unit {
+                CountUnit::Records => stats.search.patterns.records[idx],
+                CountUnit::Occurrences => stats.search.patterns.occurrences[idx],
+            };
+            if normalize {
+                let per_million = if stats.search.nb_records_tot > 0 {
+                    count as f64 / stats.search.nb_records_tot as f64 * 1e6
+                } else {
+                    0.0
+                };
+                write!(writer, "\t{per_million:.4}")?;
+            } else {
+                write!(writer, "\t{count}")?;
+            }
+        }
+        writeln!(writer)?;
+    }
+    writer
+        .flush()
+        .with_context(|| format!("Problem writing count matrix file: {}", path.display()))
+}
+
+/// Search the records of one sample and write the selected records to
+/// `out_path` (stdout if not given) and the other records to
+/// `unmatched_path` if given. Paired-end reads are written to two files with
+/// '_1' and '_2' suffixes, unless the output is interleaved.
+fn extract_sample(
+    args: &CmdExtract,
+    sample: &SampleEntry,
+    out_path: Option<&Path>,
+    unmatched_path: Option<&Path>,
+    ctx: &mut SearchContext,
+) -> Result<SampleStats> {
+    let mut stats = SampleStats::new(ctx.pattern_stats.clone());
+    let logging_active = ctx.logging_active;
+
+    // Check if file paths point to directories and gets the file names
+    error_if_directory(&sample.in_1, "Record file path")?;
+    let in_fastx_filename = sample.in_1.file_name().unwrap().to_str().unwrap();
+    let in_fastq_2_filename = match &sample.in_2 {
+        Some(p) => {
+            error_if_directory(p, "Second read file path")?;
+            p.file_name().unwrap().to_str().unwrap()
+        },
+        None if args.interleaved => in_fastx_filename,
+        None => "",
+    };
+    let paired = sample.in_2.is_some() || args.interleaved;
+
+    // When splitting the output, records are written to the files of the
+    // labels of their hits instead of a single output file
+    let split_output = ctx.demux.is_some() && out_path.is_some();
+    let mut record_hits: Vec<(usize, usize, usize)> = Vec::new();
+    // Hits in the current read as (position, pattern index)
+    let mut hits: Vec<(usize, usize)> = Vec::new();
+
+    // Uses a gzip decoder or regular file reader to read FASTQ/A records,
+    // depending on the file extension
+    let mut reader = needletail::parse_fastx_file(&sample.in_1)
+        .with_context(|| format!("Invalid FASTQ/A input path or file: {:?}", sample.in_1))?;
+
+    //
+    // If no second file is provided, process single file
+    if !paired {
+        // Either write to file or stdout if no output path is provided
+        let mut writer = match out_path {
+            Some(_) if split_output => Box::new(io::sink()) as Box<dyn io::Write>,
+            Some(path) => {
+                let file = fs::File::create(path).with_context(|| {
+                    format!("Error writing to output file; no such directory: {path:?}")
+                })?;
+                Box::new(BufWriter::new(file)) as Box<dyn io::Write>
+            }
+            None => {
+                let stdout = io::stdout();
+                Box::new(BufWriter::new(stdout)) as Box<dyn io::Write>
+            }
+        };
+
+        let mut demux_writers = match out_path {
+            Some(path) if split_output => Some(DemuxWriters::new(path, false)),
+            _ => None,
         };
+        let mut unmatched_writer = unmatched_path.map(create_file_writer).transpose()?;
+        // Copy of the current record with masked or trimmed hits
+        let mut edited_record = OwnedRecord::new();
 
         // Iterate over FASTA/Q records and check for k-mer presence
         while let Some(r) = reader.next() {
             let record = r.with_context(|| "Error during FASTQ/A record parsing.")?;
             let mut found_occ = false;
-
-            if logging_active {
-                nb_records_tot += 1;
-                nb_bases += record.num_bases();
-            }
-
-            // Get occurrences of k-mers in the sequence using Aho-Corasick
-            if let Some(ac) = ac.as_ref() {
-                for mat in ac.find_overlapping_iter(&record.seq()) {
-                    if !logging_active {
-                        found_occ = true;
-                        break;
-                    } else {
-                        if logging_active {
-                            logger.log_fields(
-                                in_fastx_filename,
-                                record.id(),
-                                &pattern_list[mat.pattern().as_usize()],
-                                mat.start(),
-                            );
-                            if let Some(jl) = &mut json_logger {
-                                jl.log_fields(
-                                    in_fastx_filename,
-                                    record.id(),
-                                    &pattern_list[mat.pattern().as_usize()],
-                                    mat.start(),
-                                );
-                            }
-                        }
-                        pattern_hit_counts[mat.pattern().as_usize()] += 1;
-                        nb_hits_tot.0 += 1;
-                        found_occ = true;
+            record_hits.clear();
+
+            // Records failing the pre-filters are not searched
+            let seq = record.seq();
+            if !ctx.search.record_filter.passes(&seq) {
+                stats.search.nb_records_skipped += 1;
+            // If logging active, find all hits and report them in the same
+            // order for both algorithms
+            } else if logging_active {
+                if let Some(record_nb) = ctx.search.search(&mut stats.search, &seq, record.qual(), None, &mut hits) {
+                    for &(pos, idx) in &hits {
+                        ctx.report_hit(in_fastx_filename, record.id(), record_nb, (pos, idx))?;
+                        record_hits.push((0, pos, idx));
                     }
                 }
+                stats.nb_hits_in_file[0] += hits.len();
+                found_occ = !hits.is_empty();
                 if found_occ {
-                    nb_records_hit.0 += 1;
+                    stats.nb_records_hit_in_file[0] += 1;
                 }
-            // Or use BNDMq
+            // If logging disabled, only search for a match and stop if found
             } else {
-                // If logging active, search for matching positions and print them
-                if logging_active {
-                    for (idx, (pattern, bndmq)) in bndmq_collection.iter().enumerate() {
-                        let mut found_any = false;
-                        for o in bndmq.find_iter(&record.seq()) {
-                            found_any = true;
-                            logger.log_fields(
-                                in_fastx_filename,
-                                record.id(),
-                                pattern,
-                                o,
-                            );
-                            if let Some(jl) = &mut json_logger {
-                                jl.log_fields(in_fastx_filename, record.id(), pattern, o);
-                            }
-                            nb_hits_tot.0 += 1;
-                        }
-                        if found_any {
-                            found_occ = true;
-                            pattern_hit_counts[idx] += 1;
-                        }
-                    }
-                    if found_occ {
-                        nb_records_hit.0 += 1;
-                    }
-                // If logging disabled, only search for a match and break if found
-                } else {
-                    for (_, bndmq) in &bndmq_collection {
-                        if bndmq.find_match(&record.seq()) {
-                            found_occ = true;
-                            break;
-                        }
-                    }
-                }
+                found_occ = ctx.search.has_hit(&seq, record.qual());
             }
 
             // Write record to file or stdout if any k-mer has been found
             if found_occ != args.invert_match {
-                nb_records_extracted += 1;
-                if !args.suppress_output {
-                    record.write(&mut writer, None).unwrap();
+                stats.nb_records_extracted += 1;
+                if let Some(edit) = ctx.hit_edit {
+                    edited_record.copy_from(&record);
+                    edited_record.edit_hits(edit, &hit_ranges(&record_hits, 0, ctx.pattern_list));
+                }
+                let write_selected = |writer: &mut dyn io::Write| -> Result<()> {
+                    if let Some(flank) = args.flank {
+                        write_flank_windows(&record, &record_hits, ctx.pattern_list, flank, writer)
+                    } else if ctx.hit_edit.is_some() {
+                        edited_record.write(writer)
+                    } else {
+                        record.write(writer, None).with_context(|| "Error writing record to output file.")
+                    }
+                };
+                if let (Some(demux), Some(demux_writers)) = (ctx.demux, demux_writers.as_mut()) {
+                    for label in demux.targets(&record_hits) {
+                        let (label_writer, _) = demux_writers.get(demux, label)?;
+                        write_selected(label_writer)?;
+                    }
+                } else if !args.suppress_output {
+                    write_selected(&mut writer)?;
                 }
+            } else if let Some(unmatched_writer) = unmatched_writer.as_mut() {
+                record
+                    .write(unmatched_writer, None)
+                    .with_context(|| "Error writing record to unmatched output file.")?;
             }
         }
+
+        writer.flush().with_context(|| "Error writing to output file.")?;
+        if let Some(demux_writers) = demux_writers.as_mut() {
+            demux_writers.flush_all().with_context(|| "Error writing to split output files.")?;
+        }
+        if let Some(unmatched_writer) = unmatched_writer.as_mut() {
+            unmatched_writer.flush().with_context(|| "Error writing to unmatched output file.")?;
+        }
     ////
     //// ---------------------- Handling Paired-End Reads ---------------------
     ////
-    // If a second file is provided, process paired-end reads
+    // If a second file is provided or the input is interleaved, process paired-end reads
     } else {
-        let mut reader_2 = needletail::parse_fastx_file(args.in_fastq_2.clone().unwrap())
-            .with_context(|| {
-                format!(
-                    "Invalid second FASTQ input path or file: {:?}",
-                    &args.in_fastq_2
-                )
-            })?;
+        let mut pair_reader = match &sample.in_2 {
+            Some(in_fastq_2) => {
+                let reader_2 = needletail::parse_fastx_file(in_fastq_2).with_context(|| {
+                    format!("Invalid second FASTQ input path or file: {in_fastq_2:?}")
+                })?;
+                PairReader::Separate(reader, reader_2)
+            }
+            None => PairReader::Interleaved(reader),
+        };
 
         // Either write to file or stdout if no output path is provided;
-        // the file format is determined by the input file;
-        // write to two files with _1 and _2 suffixes for paired-end reads
-        let mut writer = match &args.out_fastx {
-            Some(pathbuf) => {
-                let pathbuf =
-                    pathbuf.with_extension(identify_uncompressed_type(&args.in_fastx).unwrap());
-                let pathbuf = add_suffix_to_file_prefix(&pathbuf, "_1");
+        // write to two files with _1 and _2 suffixes for paired-end reads,
+        // unless the output is interleaved (always the case for stdout)
+        let interleaved_out = args.interleaved || args.interleaved_out || out_path.is_none();
+        let mut writer = match out_path {
+            Some(_) if split_output => Box::new(io::sink()) as Box<dyn io::Write>,
+            Some(path) => {
+                let pathbuf = if interleaved_out {
+                    path.to_path_buf()
+                } else {
+                    add_suffix_to_file_prefix(path, "_1")
+                };
                 let path = Path::new(&pathbuf);
                 let file = fs::File::create(path).with_context(|| {
-                    format!(
-                        "Error writing to paired-end file; no such directory: {path:?}",
-                        
-                    )
+                    format!("Error writing to paired-end file; no such directory: {path:?}")
                 })?;
                 Box::new(BufWriter::new(file)) as Box<dyn io::Write>
             }
@@ -439,281 +1695,161 @@ pub fn extract_records(args: CmdExtract) -> Result<()> {
                 Box::new(BufWriter::new(stdout)) as Box<dyn io::Write>
             }
         };
-        let mut writer2 = match args.out_fastx {
-            Some(pathbuf) => {
-                let pathbuf =
-                    pathbuf.with_extension(identify_uncompressed_type(&args.in_fastx).unwrap());
-                let pathbuf = add_suffix_to_file_prefix(&pathbuf, "_2");
+        let mut demux_writers = match out_path {
+            Some(path) if split_output => Some(DemuxWriters::new(path, !interleaved_out)),
+            _ => None,
+        };
+        // Records that are not selected go to the unmatched output, split
+        // into two files like the selected ones
+        let mut unmatched_writers = match unmatched_path {
+            Some(path) if interleaved_out => Some((create_file_writer(path)?, None)),
+            Some(path) => Some((
+                create_file_writer(&add_suffix_to_file_prefix(path, "_1"))?,
+                Some(create_file_writer(&add_suffix_to_file_prefix(path, "_2"))?),
+            )),
+            None => None,
+        };
+        let mut writer2 = match out_path {
+            Some(path) if !interleaved_out && !split_output => {
+                let pathbuf = add_suffix_to_file_prefix(path, "_2");
                 let path = Path::new(&pathbuf);
                 let file = fs::File::create(path).with_context(|| {
-                    format!(
-                        "Error writing second paired-end file; no such directory: {path:?}",
-                        
-                    )
+                    format!("Error writing second paired-end file; no such directory: {path:?}")
                 })?;
-                Box::new(BufWriter::new(file)) as Box<dyn io::Write>
-            }
-            None => {
-                let stdout = io::stdout();
-                Box::new(BufWriter::new(stdout)) as Box<dyn io::Write>
+                Some(Box::new(BufWriter::new(file)) as Box<dyn io::Write>)
             }
+            _ => None,
         };
 
-        // Iterate over FASTQ records and check for k-mer presence
-        while let Some(r) = reader.next() {
-            let record_1 = r.with_context(|| "Error during FASTQ record parsing of first file.")?;
-            let record_2 = reader_2
-                .next()
-                .with_context(|| "Error during FASTQ record parsing of second file. Do the two input files contain the same number of records?")?
-                .unwrap();
-            let mut found_occ = false;
+        // Only search the reads considered by the pair mode
+        let search_read_1 = args.pair_mode != PairMode::R2;
+        let search_read_2 = args.pair_mode != PairMode::R1;
+        let filenames = [in_fastx_filename, in_fastq_2_filename];
 
-            if logging_active {
-                nb_records_tot += 2;
-                nb_bases += record_1.num_bases();
-                nb_bases += record_2.num_bases();
+        // Iterate over pairs of FASTQ records and check for k-mer presence
+        let mut pair = [OwnedRecord::new(), OwnedRecord::new()];
+        let mut nb_pairs = 0;
+        while pair_reader.read_pair(&mut pair)? {
+            nb_pairs += 1;
+
+            // Make sure both reads belong to the same fragment
+            if args.pair_name_check != PairNameCheck::Off
+                && read_name_base(&pair[0].id) != read_name_base(&pair[1].id)
+            {
+                let names = format!(
+                    "'{}' and '{}'",
+                    String::from_utf8_lossy(&pair[0].id),
+                    String::from_utf8_lossy(&pair[1].id)
+                );
+                if args.pair_name_check == PairNameCheck::Strict {
+                    anyhow::bail!(
+                        "Read names of pair {nb_pairs} do not match: {names}. Are the paired-end read files sorted the same way?"
+                    );
+                }
+                if stats.nb_pairs_name_mismatch == 0 {
+                    eprintln!(
+                        "Warning: read names of pair {nb_pairs} do not match: {names}. Further mismatches are only counted."
+                    );
+                }
+                stats.nb_pairs_name_mismatch += 1;
             }
 
-            // Get occurrences of patterns in the sequence using Aho-Corasick
-            if let Some(ac) = ac.as_ref() {
-                let mut record_hit: (usize, usize) = (0, 0);
-                for mat in ac.find_overlapping_iter(&record_1.seq()) {
-                    if !logging_active {
-                        found_occ = true;
-                        break;
-                    } else {
-                        if logging_active {
-                            logger.log_fields(
-                                in_fastx_filename,
-                                record_1.id(),
-                                &pattern_list[mat.pattern().as_usize()],
-                                mat.start(),
-                            );
-                            if let Some(jl) = &mut json_logger {
-                                jl.log_fields(
-                                    in_fastx_filename,
-                                    record_1.id(),
-                                    &pattern_list[mat.pattern().as_usize()],
-                                    mat.start(),
-                                );
-                            }
-                        }
-                        pattern_hit_counts[mat.pattern().as_usize()] += 1;
-                        record_hit.0 = 1;
-                        nb_hits_tot.0 += 1;
-                        found_occ = true;
-                    }
+            let records = [&pair[0], &pair[1]];
+            let mut record_hit = [false, false];
+            record_hits.clear();
+
+            for mate in 0..2 {
+                if (mate == 0 && !search_read_1) || (mate == 1 && !search_read_2) {
+                    continue;
                 }
-                for mat in ac.find_overlapping_iter(&record_2.seq()) {
-                    if !logging_active {
-                        found_occ = true;
-                        break;
-                    } else {
-                        if logging_active {
-                            logger.log_fields(
-                                in_fastq_2_filename,
-                                record_2.id(),
-                                &pattern_list[mat.pattern().as_usize()],
-                                mat.start(),
-                            );
-                            if let Some(jl) = &mut json_logger {
-                                jl.log_fields(
-                                    in_fastq_2_filename,
-                                    record_2.id(),
-                                    &pattern_list[mat.pattern().as_usize()],
-                                    mat.start(),
-                                );
-                            }
-                        }
-                        pattern_hit_counts[mat.pattern().as_usize()] += 1;
-                        record_hit.1 = 1;
-                        nb_hits_tot.1 += 1;
-                        found_occ = true;
-                    }
+                // Without logging, skip the second read if the pair is already decided
+                if mate == 1 && !logging_active && !args.pair_mode.needs_read_2(record_hit[0]) {
+                    break;
                 }
-                if logging_active {
-                    nb_records_hit.0 += record_hit.0;
-                    nb_records_hit.1 += record_hit.1;
+                let record = records[mate];
+                let seq = &record.seq;
+                let qual = record.qual.as_deref();
+
+                // Reads failing the pre-filters are not searched
+                if !ctx.search.record_filter.passes(seq) {
+                    stats.search.nb_records_skipped += 1;
+                    continue;
                 }
-            // Or use BNDMq
-            } else {
-                // If logging active, search for matching positions and print them
-                if logging_active {
-                    let mut record_hit: (usize, usize) = (0, 0);
-                    for (idx, (pattern, bndmq)) in bndmq_collection.iter().enumerate() {
-                        let mut found_any1 = false;
-                        let mut found_any2 = false;
-
-                        for o in bndmq.find_iter(&record_1.seq()) {
-                            found_any1 = true;
-                            logger.log_fields(
-                                in_fastx_filename,
-                                record_1.id(),
-                                pattern,
-                                o,
-                            );
-                            if let Some(jl) = &mut json_logger {
-                                jl.log_fields(in_fastx_filename, record_1.id(), pattern, o);
-                            }
-                            nb_hits_tot.0 += 1;
-                        }
-
-                        for o in bndmq.find_iter(&record_2.seq()) {
-                            found_any2 = true;
-                            logger.log_fields(
-                                in_fastq_2_filename,
-                                record_2.id(),
-                                pattern,
-                                o,
-                            );
-                            if let Some(jl) = &mut json_logger {
-                                jl.log_fields(in_fastq_2_filename, record_2.id(), pattern, o);
-                            }
-                            nb_hits_tot.1 += 1;
-                        }
 
-                        if found_any1 {
-                            found_occ = true;
-                            record_hit.0 = 1;
-                            pattern_hit_counts[idx] += 1;
-                        }
-                        if found_any2 {
-                            found_occ = true;
-                            record_hit.1 = 1;
-                            pattern_hit_counts[idx] += 1;
+                // If logging active, find all hits and report them in the
+                // same order for both algorithms
+                if logging_active {
+                    if let Some(record_nb) = ctx.search.search(&mut stats.search, seq, qual, Some(nb_pairs), &mut hits) {
+                        for &(pos, idx) in &hits {
+                            ctx.report_hit(filenames[mate], &record.id, record_nb, (pos, idx))?;
+                            record_hits.push((mate, pos, idx));
                         }
                     }
-                    nb_records_hit.0 += record_hit.0;
-                    nb_records_hit.1 += record_hit.1;
-                // If logging disabled, only search for a match and break if found
+                    stats.nb_hits_in_file[mate] += hits.len();
+                    record_hit[mate] = !hits.is_empty();
+                // If logging disabled, only search for a match and stop if found
                 } else {
-                    for (_, bndmq) in &bndmq_collection {
-                        if bndmq.find_match(&record_1.seq()) || bndmq.find_match(&record_2.seq()) {
-                            found_occ = true;
-                            break;
-                        }
-                    }
+                    record_hit[mate] = ctx.search.has_hit(seq, qual);
                 }
             }
 
-            // Write records to file or stdout if any patterns have been matched
-            if found_occ != args.invert_match {
-                nb_records_extracted += 2;
-                if !args.suppress_output {
-                    record_1.write(&mut writer, None).unwrap();
-                    record_2.write(&mut writer2, None).unwrap();
+            if logging_active {
+                stats.nb_records_hit_in_file[0] += record_hit[0] as usize;
+                stats.nb_records_hit_in_file[1] += record_hit[1] as usize;
+                match record_hit {
+                    [true, true] => stats.nb_pairs_hit.both += 1,
+                    [true, false] => stats.nb_pairs_hit.read_1_only += 1,
+                    [false, true] => stats.nb_pairs_hit.read_2_only += 1,
+                    [false, false] => stats.nb_pairs_hit.none += 1,
                 }
             }
+
+            // Write records to file or stdout if the pair is selected by the pair mode
+            if args.pair_mode.selects(record_hit[0], record_hit[1]) != args.invert_match {
+                stats.nb_records_extracted += 2;
+                if let Some(edit) = ctx.hit_edit {
+                    for (mate, record) in pair.iter_mut().enumerate() {
+                        record.edit_hits(edit, &hit_ranges(&record_hits, mate, ctx.pattern_list));
+                    }
+                }
+                if let (Some(demux), Some(demux_writers)) = (ctx.demux, demux_writers.as_mut()) {
+                    for label in demux.targets(&record_hits) {
+                        let (label_writer, label_writer2) = demux_writers.get(demux, label)?;
+                        pair[0].write(label_writer)?;
+                        pair[1].write(label_writer2.as_mut().unwrap_or(label_writer))?;
+                    }
+                } else if !args.suppress_output {
+                    pair[0].write(&mut writer)?;
+                    pair[1].write(writer2.as_mut().unwrap_or(&mut writer))?;
+                }
+            } else if let Some((unmatched_writer, unmatched_writer2)) = unmatched_writers.as_mut() {
+                pair[0].write(unmatched_writer)?;
+                pair[1].write(unmatched_writer2.as_mut().unwrap_or(unmatched_writer))?;
+            }
         }
-        if reader_2.next().is_some() {
-            anyhow::bail!(
-                "The two input files have a different number of records. Please provide valid paired-end read files."
-            );
-        }
-    }
 
-    // Log summary statistics as plain text and/or JSON
-    if logging_active {
-        logger.flush();
-        let nb_patterns_found = pattern_hit_counts
-            .iter()
-            .filter(|&&count| count > 0)
-            .count();
-        let nb_patterns_found_percentage =
-            nb_patterns_found as f64 / pattern_hit_counts.len() as f64 * 100.0;
-        logger.write_header(&format!(
-            "#\n#Number of patterns found: {}/{} ({:.2} %)\n",
-            nb_patterns_found,
-            pattern_hit_counts.len(),
-            nb_patterns_found_percentage,
-        ));
-        logger.write_header("#Pattern\tCount\n");
-        for (pattern, count) in pattern_list.iter().zip(pattern_hit_counts.iter()) {
-            logger.write_header(&format!("#{pattern}\t{count}\n"));
+        writer.flush().with_context(|| "Error writing to output file.")?;
+        if let Some(writer2) = writer2.as_mut() {
+            writer2.flush().with_context(|| "Error writing to second paired-end output file.")?;
         }
-        logger.write_header(&format!(
-            "#\n#Total number of records searched: {nb_records_tot}\n"
-        ));
-        logger.write_header(&format!(
-            "#Total number of characters searched: {nb_bases}\n"
-        ));
-        logger.write_header(&format!(
-            "#Total number of hits: {}\n",
-            nb_hits_tot.0 + nb_hits_tot.1
-        ));
-        logger.write_header(&format!(
-            "#Number of distinct records with a hit: {}\n",
-            nb_records_hit.0 + nb_records_hit.1
-        ));
-        if args.in_fastq_2.is_some() {
-            logger.write_header(&format!(
-                "#\n#Total number of hits in file 1: {}\n",
-                nb_hits_tot.0
-            ));
-            logger.write_header(&format!(
-                "#Total number of hits in file 2: {}\n",
-                nb_hits_tot.1
-            ));
-            logger.write_header(&format!(
-                "#Number of distinct records with a hit in file 1: {}\n",
-                nb_records_hit.0
-            ));
-            logger.write_header(&format!(
-                "#Number of distinct records with a hit in file 2: {}\n",
-                nb_records_hit.1
-            ));
-            logger.write_header(&format!(
-                "#Total number of extracted records: {nb_records_extracted}\n"
-            ));
+        if let Some(demux_writers) = demux_writers.as_mut() {
+            demux_writers.flush_all().with_context(|| "Error writing to split output files.")?;
+        }
+        if let Some((unmatched_writer, unmatched_writer2)) = unmatched_writers.as_mut() {
+            unmatched_writer.flush().with_context(|| "Error writing to unmatched output file.")?;
+            if let Some(unmatched_writer2) = unmatched_writer2 {
+                unmatched_writer2.flush().with_context(|| "Error writing to unmatched output file.")?;
+            }
+        }
+        if stats.nb_pairs_name_mismatch > 0 {
+            eprintln!(
+                "Warning: {} of {nb_pairs} read pairs of sample '{}' have mismatching read names.",
+                stats.nb_pairs_name_mismatch, sample.name
+            );
         }
-        logger.flush();
-    }
-
-    // Finalize JSON log if active
-    if let Some(jl) = json_logger {
-        let input_files_json = serde_json::json!({
-            "kmer_file": args.kmer_file.as_ref().map(|p| p.to_string_lossy().to_string()),
-            "record_file_1": in_fastx_filename,
-            "record_file_2": if args.in_fastq_2.is_some() { Some(in_fastq_2_filename) } else { None },
-        });
-        let pattern_hit_counts_map: HashMap<String, u32> =
-            pattern_list.iter().cloned().zip(pattern_hit_counts.iter().copied()).collect();
-        let meta_information = serde_json::json!({
-            "program": crate_name!(),
-            "version": crate_version!(),
-            "timestamp": Zoned::now().round(Unit::Second).unwrap(),
-            "subcommand": "extract",
-            "command_line": env::args().collect::<Vec<String>>(),
-            "search_algorithm": if args.aho_corasick { "Aho-Corasick" } else { "BNDMq" },
-            "inverted_matching": args.invert_match,
-            "case_insensitive": args.case_insensitive,
-            "input_files": input_files_json,
-        });
-        let summary_statistics = serde_json::json!({
-            "number_of_patterns_searched": pattern_list.len(),
-            "number_of_patterns_found": pattern_hit_counts.iter().filter(|&&count| count > 0).count(),
-            "number_of_records_searched": nb_records_tot,
-            "number_of_characters_searched": nb_bases,
-            "number_of_matches": nb_hits_tot.0 + nb_hits_tot.1,
-            "number_of_distinct_records_with_a_hit": nb_records_hit.0 + nb_records_hit.1,
-        });
-        let paired_end_stats = serde_json::json!({
-            "searching_paired_end_reads": args.in_fastq_2.is_some(),
-            "number_of_hits_in_file_1": nb_hits_tot.0,
-            "number_of_hits_in_file_2": if args.in_fastq_2.is_some() { Some(nb_hits_tot.1) } else { None },
-            "number_of_distinct_records_with_a_hit_in_file_1": nb_records_hit.0,
-            "number_of_distinct_records_with_a_hit_in_file_2": if args.in_fastq_2.is_some() { Some(nb_records_hit.1) } else { None },
-            "number_of_extracted_records": nb_records_extracted,
-        });
-        jl.finalize(
-            &meta_information,
-            &serde_json::json!(pattern_hit_counts_map),
-            &summary_statistics,
-            Some(&paired_end_stats),
-        );
     }
 
-    Ok(())
+    Ok(stats)
 }
 
 //
@@ -726,10 +1862,60 @@ mod tests {
     use std::fs;
     use std::path::Path;
 
-    /// Compare FASTA output with fixture
-    fn compare_fasta_output(actual_path: &Path, expected_path: &str) -> Result<()> {
-        let expected = fs::read_to_string(expected_path)?;
-        let actual = fs::read_to_string(actual_path)?;
+    /// Options with the defaults of the command line, to be completed by
+    /// each test.
+    fn test_args() -> CmdExtract {
+        CmdExtract {
+            in_fastx: vec![],
+            in_fastq_2: vec![],
+            sample_sheet: None,
+            interleaved: false,
+            interleaved_out: false,
+            pair_mode: PairMode::Either,
+            pair_name_check: PairNameCheck::Off,
+            kmer_seq: None,
+            kmer_file: None,
+            out_fastx: None,
+            reverse_complement: false,
+            canonical: false,
+            out_log: None,
+            log_level: LogLevel::Hit,
+            json_log: None,
+            json_format: JsonFormat::Pretty,
+            count_matrix: None,
+            count_unit: CountUnit::Records,
+            normalize_counts: false,
+            split_by: None,
+            multi_hit: MultiHit::Duplicate,
+            unmatched_out: None,
+            mask: None,
+            trim: None,
+            flank: None,
+            bed_out: None,
+            gff_out: None,
+            hits_parquet: None,
+            min_base_qual: None,
+            min_mean_qual: None,
+            min_length: None,
+            max_length: None,
+            max_n_fraction: None,
+            suppress_output: false,
+            invert_match: false,
+            case_insensitive: false,
+            lowercase: false,
+            uppercase: false,
+            q_size: None,
+            aho_corasick: false,
+            config: None,
+            preset: None,
+            config_values: ConfigValues::default(),
+        }
+    }
+
+    /// Compare FASTA output with fixture
+    fn compare_fasta_output(actual_path: &Path, expected_path: &str) -> Result<()> {
+        let expected = fs::read_to_string(expected_path)?;
+        let actual = fs::read_to_string(actual_path)?;
         assert_eq!(expected, actual, "FASTA output does not match fixture");
         Ok(())
     }
@@ -892,22 +2078,13 @@ mod tests {
 
         // Run the extract command
         let args = CmdExtract {
-            in_fastx: PathBuf::from("tests/fixtures/input/simple.fasta"),
-            in_fastq_2: None,
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/simple.fasta")],
             kmer_seq: Some(vec!["ACG".to_string()]),
-            kmer_file: None,
             out_fastx: Some(out_fasta.clone()),
-            q_size: None,
-            aho_corasick: false,
             reverse_complement: true,
-            canonical: false,
             out_log: Some(out_log.clone()),
-            suppress_output: false,
             json_log: Some(out_json.clone()),
-            invert_match: false,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         extract_records(args)?;
@@ -932,22 +2109,14 @@ mod tests {
 
         // Run the extract command with inverted matching
         let args = CmdExtract {
-            in_fastx: PathBuf::from("tests/fixtures/input/simple.fasta"),
-            in_fastq_2: None,
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/simple.fasta")],
             kmer_seq: Some(vec!["ACG".to_string()]),
-            kmer_file: None,
             out_fastx: Some(out_fasta.clone()),
-            q_size: None,
-            aho_corasick: false,
             reverse_complement: true,
-            canonical: false,
             out_log: Some(out_log.clone()),
-            suppress_output: false,
             json_log: Some(out_json.clone()),
             invert_match: true,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         extract_records(args)?;
@@ -975,22 +2144,12 @@ mod tests {
 
         // Run the extract command
         let args = CmdExtract {
-            in_fastx: PathBuf::from("tests/fixtures/input/fixed-width.faa"),
-            in_fastq_2: None,
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/fixed-width.faa")],
             kmer_seq: Some(vec!["DKAT".to_string()]),
-            kmer_file: None,
             out_fastx: Some(out_fasta.clone()),
-            q_size: None,
-            aho_corasick: false,
-            reverse_complement: false,
-            canonical: false,
             out_log: Some(out_log.clone()),
-            suppress_output: false,
             json_log: Some(out_json.clone()),
-            invert_match: false,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         extract_records(args)?;
@@ -1020,22 +2179,13 @@ mod tests {
 
         // Run the extract command
         let args = CmdExtract {
-            in_fastx: PathBuf::from("tests/fixtures/input/paired-1.fastq"),
-            in_fastq_2: Some(PathBuf::from("tests/fixtures/input/paired-2.fastq")),
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-1.fastq")],
+            in_fastq_2: vec![PathBuf::from("tests/fixtures/input/paired-2.fastq")],
             kmer_seq: Some(vec!["CTT".to_string()]),
-            kmer_file: None,
             out_fastx: Some(out_base),
-            q_size: None,
-            aho_corasick: false,
-            reverse_complement: false,
-            canonical: false,
             out_log: Some(out_log.clone()),
-            suppress_output: false,
             json_log: Some(out_json.clone()),
-            invert_match: false,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         extract_records(args)?;
@@ -1054,4 +2204,744 @@ mod tests {
 
         Ok(())
     }
+
+    // Require hits in both reads of a pair; no pair of the fixture qualifies
+    #[test]
+    fn test_extract_paired_pair_mode_both() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_base = temp_dir.path().join("out");
+        let out_json = temp_dir.path().join("out.json");
+
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-1.fastq")],
+            in_fastq_2: vec![PathBuf::from("tests/fixtures/input/paired-2.fastq")],
+            pair_mode: PairMode::Both,
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            out_fastx: Some(out_base),
+            json_log: Some(out_json.clone()),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        assert_eq!(fs::read_to_string(temp_dir.path().join("out_1.fastq"))?, "");
+        assert_eq!(fs::read_to_string(temp_dir.path().join("out_2.fastq"))?, "");
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out_json)?)?;
+        let stats = &json["paired_end_reads_statistics"];
+        assert_eq!(stats["pair_mode"], "both");
+        assert_eq!(stats["number_of_extracted_records"], 0);
+        assert_eq!(stats["number_of_pairs_with_a_hit_in_read_1_only"], 1);
+        assert_eq!(stats["number_of_pairs_with_a_hit_in_read_2_only"], 1);
+
+        Ok(())
+    }
+
+    // Only consider the second read and invert the matching
+    #[test]
+    fn test_extract_paired_pair_mode_r2_inverted() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_base = temp_dir.path().join("out");
+
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-1.fastq")],
+            in_fastq_2: vec![PathBuf::from("tests/fixtures/input/paired-2.fastq")],
+            pair_mode: PairMode::R2,
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            out_fastx: Some(out_base),
+            aho_corasick: true,
+            invert_match: true,
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        let out_1 = fs::read_to_string(temp_dir.path().join("out_1.fastq"))?;
+        let out_2 = fs::read_to_string(temp_dir.path().join("out_2.fastq"))?;
+        assert_eq!(out_1, "@seq1/1\nACTTACGT\n+\nIIIIIIII\n");
+        assert_eq!(out_2, "@seq1/2\nGCTATAAT\n+\nIIIIIIII\n");
+
+        Ok(())
+    }
+
+    // Interleaved input is written to a single interleaved output file
+    // Corresponds to: cargo run -- extract -i tests/fixtures/input/paired-interleaved.fastq --interleaved -s CTT -o out.fastq
+    #[test]
+    fn test_extract_interleaved_input() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_fastq = temp_dir.path().join("out.fastq");
+        let out_json = temp_dir.path().join("out.json");
+
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-interleaved.fastq")],
+            interleaved: true,
+            pair_mode: PairMode::R1,
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            out_fastx: Some(out_fastq.clone()),
+            json_log: Some(out_json.clone()),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        assert_eq!(
+            fs::read_to_string(&out_fastq)?,
+            "@seq1/1\nACTTACGT\n+\nIIIIIIII\n@seq1/2\nGCTATAAT\n+\nIIIIIIII\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out_json)?)?;
+        let stats = &json["paired_end_reads_statistics"];
+        assert_eq!(stats["searching_paired_end_reads"], true);
+        assert_eq!(stats["number_of_hits_in_file_1"], 1);
+        assert_eq!(stats["number_of_extracted_records"], 2);
+
+        Ok(())
+    }
+
+    // Mates with the same ID get a line each in the record-level log
+    #[test]
+    fn test_extract_interleaved_record_log_same_ids() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let in_fastq = temp_dir.path().join("in.fastq");
+        let out_log = temp_dir.path().join("out.log");
+        fs::write(&in_fastq, "@read1\nACTTACGT\n+\nIIIIIIII\n@read1\nAAACTTCA\n+\nIIIIIIII\n")?;
+
+        let args = CmdExtract {
+            in_fastx: vec![in_fastq.clone()],
+            interleaved: true,
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            out_log: Some(out_log.clone()),
+            log_level: LogLevel::Record,
+            suppress_output: true,
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        let log = fs::read_to_string(&out_log)?;
+        let table: Vec<&str> = log.lines().filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(table, ["in.fastq\tread1\t1\tCTT\t1", "in.fastq\tread1\t1\tCTT\t3"]);
+
+        Ok(())
+    }
+
+    // Two input files written to a single interleaved output file
+    #[test]
+    fn test_extract_paired_interleaved_output() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_fastq = temp_dir.path().join("out.fastq");
+
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-1.fastq")],
+            in_fastq_2: vec![PathBuf::from("tests/fixtures/input/paired-2.fastq")],
+            interleaved_out: true,
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            out_fastx: Some(out_fastq.clone()),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        assert_eq!(
+            fs::read_to_string(&out_fastq)?,
+            fs::read_to_string("tests/fixtures/input/paired-interleaved.fastq")?
+        );
+        assert!(!temp_dir.path().join("out_1.fastq").exists());
+
+        Ok(())
+    }
+
+    // Mates in a different order are counted in lenient mode, and not
+    // checked by default
+    #[test]
+    fn test_extract_paired_name_check_lenient() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_json = temp_dir.path().join("out.json");
+
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-1.fastq")],
+            in_fastq_2: vec![PathBuf::from("tests/fixtures/input/paired-2-unsorted.fastq")],
+            pair_name_check: PairNameCheck::Lenient,
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            suppress_output: true,
+            json_log: Some(out_json.clone()),
+            ..test_args()
+        };
+
+        extract_records(args.clone())?;
+
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out_json)?)?;
+        let stats = &json["paired_end_reads_statistics"];
+        assert_eq!(stats["pair_name_check"], "lenient");
+        assert_eq!(stats["number_of_pairs_with_mismatching_read_names"], 2);
+
+        extract_records(CmdExtract { pair_name_check: PairNameCheck::Off, ..args })?;
+
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out_json)?)?;
+        let stats = &json["paired_end_reads_statistics"];
+        assert_eq!(stats["pair_name_check"], "off");
+        assert!(stats["number_of_pairs_with_mismatching_read_names"].is_null());
+
+        Ok(())
+    }
+
+    // Mates in a different order stop the run in strict mode
+    #[test]
+    fn test_extract_paired_name_check_strict() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let out_json = temp_dir.path().join("out.json");
+
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-1.fastq")],
+            in_fastq_2: vec![PathBuf::from("tests/fixtures/input/paired-2-unsorted.fastq")],
+            pair_name_check: PairNameCheck::Strict,
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            suppress_output: true,
+            json_log: Some(out_json),
+            ..test_args()
+        };
+
+        let err = extract_records(args).unwrap_err();
+        assert!(err.to_string().contains("do not match"));
+    }
+
+    // Samples from a sample sheet are written to separate files in the output directory
+    // Corresponds to: cargo run -- extract --sample-sheet tests/fixtures/input/samples.tsv -s CTT -o out -j out.json
+    #[test]
+    fn test_extract_sample_sheet() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_dir = temp_dir.path().join("out");
+        let out_json = temp_dir.path().join("out.json");
+
+        let args = CmdExtract {
+            sample_sheet: Some(PathBuf::from("tests/fixtures/input/samples.tsv")),
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            out_fastx: Some(out_dir.clone()),
+            json_log: Some(out_json.clone()),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        assert!(out_dir.join("simple.fasta").exists());
+        compare_fasta_output(
+            &out_dir.join("paired_1.fastq"),
+            "tests/fixtures/extract/paired_1.extracted.fastq",
+        )?;
+        compare_fasta_output(
+            &out_dir.join("paired_2.fastq"),
+            "tests/fixtures/extract/paired_2.extracted.fastq",
+        )?;
+
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out_json)?)?;
+        let samples = json["per_sample_statistics"].as_array().unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0]["sample"], "simple");
+        assert_eq!(samples[0]["searching_paired_end_reads"], false);
+        assert_eq!(samples[1]["sample"], "paired");
+        assert_eq!(samples[1]["searching_paired_end_reads"], true);
+        let summary = &json["summary_statistics"];
+        assert_eq!(
+            summary["number_of_matches"],
+            samples[0]["number_of_matches"].as_u64().unwrap()
+                + samples[1]["number_of_matches"].as_u64().unwrap()
+        );
+
+        Ok(())
+    }
+
+    // Several input files cannot be written to stdout
+    #[test]
+    fn test_extract_multiple_inputs_require_output_dir() {
+        let args = CmdExtract {
+            in_fastx: vec![
+                PathBuf::from("tests/fixtures/input/paired-1.fastq"),
+                PathBuf::from("tests/fixtures/input/paired-2.fastq"),
+            ],
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            ..test_args()
+        };
+
+        let err = extract_records(args).unwrap_err();
+        assert!(err.to_string().contains("output directory"));
+    }
+
+    // Count matrix with a column for each sample of the sample sheet
+    // Corresponds to: cargo run -- extract --sample-sheet tests/fixtures/input/samples.tsv -s CTT TTT -S --count-matrix out.tsv --count-unit occurrences
+    #[test]
+    fn test_extract_count_matrix() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+
+        for (unit, expected) in [
+            (CountUnit::Records, "pattern\tsimple\tpaired\nCTT\t0\t2\nTTT\t2\t1\n"),
+            (CountUnit::Occurrences, "pattern\tsimple\tpaired\nCTT\t0\t2\nTTT\t7\t6\n"),
+        ] {
+            let out_matrix = temp_dir.path().join("out.tsv");
+            let args = CmdExtract {
+                sample_sheet: Some(PathBuf::from("tests/fixtures/input/samples.tsv")),
+                kmer_seq: Some(vec!["CTT".to_string(), "TTT".to_string()]),
+                aho_corasick: unit == CountUnit::Occurrences,
+                suppress_output: true,
+                count_matrix: Some(out_matrix.clone()),
+                count_unit: unit,
+                ..test_args()
+            };
+
+            extract_records(args)?;
+
+            assert_eq!(fs::read_to_string(&out_matrix)?, expected);
+        }
+
+        Ok(())
+    }
+
+    // Split output by k-mer group with the three policies for records matching several groups
+    // Corresponds to: cargo run -- extract -i tests/fixtures/input/simple.fasta -f tests/fixtures/input/kmer-groups.fasta --split-by group --multi-hit multi -o out.fasta
+    #[test]
+    fn test_extract_split_by_group() -> Result<()> {
+        for (multi_hit, expected) in [
+            (
+                MultiHit::Duplicate,
+                vec![("groupA", "seq1 seq3"), ("groupB", "seq2 seq3")],
+            ),
+            (MultiHit::First, vec![("groupA", "seq1 seq3"), ("groupB", "seq2")]),
+            (
+                MultiHit::Multi,
+                vec![("groupA", "seq1"), ("groupB", "seq2"), ("multi", "seq3")],
+            ),
+        ] {
+            let temp_dir = tempfile::tempdir()?;
+            let args = CmdExtract {
+                in_fastx: vec![PathBuf::from("tests/fixtures/input/simple.fasta")],
+                kmer_file: Some(PathBuf::from("tests/fixtures/input/kmer-groups.fasta")),
+                out_fastx: Some(temp_dir.path().join("out.fasta")),
+                aho_corasick: multi_hit == MultiHit::Duplicate,
+                split_by: Some(SplitBy::Group),
+                multi_hit,
+                ..test_args()
+            };
+
+            extract_records(args)?;
+
+            let mut files: Vec<String> = fs::read_dir(temp_dir.path())?
+                .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+                .collect();
+            files.sort();
+            assert_eq!(files.len(), expected.len());
+            for (label, ids) in expected {
+                let content = fs::read_to_string(temp_dir.path().join(format!("out_{label}.fasta")))?;
+                let found: Vec<&str> = content
+                    .lines()
+                    .filter_map(|l| l.strip_prefix('>'))
+                    .collect();
+                assert_eq!(found.join(" "), ids, "{multi_hit:?} {label}");
+            }
+        }
+
+        Ok(())
+    }
+
+    // Paired-end reads split by pattern are written to two files per pattern
+    #[test]
+    fn test_extract_paired_split_by_pattern() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-1.fastq")],
+            in_fastq_2: vec![PathBuf::from("tests/fixtures/input/paired-2.fastq")],
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            out_fastx: Some(temp_dir.path().join("out")),
+            split_by: Some(SplitBy::Pattern),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        compare_fasta_output(
+            &temp_dir.path().join("out_CTT_1.fastq"),
+            "tests/fixtures/extract/paired_1.extracted.fastq",
+        )?;
+        compare_fasta_output(
+            &temp_dir.path().join("out_CTT_2.fastq"),
+            "tests/fixtures/extract/paired_2.extracted.fastq",
+        )?;
+        assert!(!temp_dir.path().join("out_1.fastq").exists());
+
+        Ok(())
+    }
+
+    // Records that are not selected are written to the unmatched output file
+    // Corresponds to: cargo run -- extract -i tests/fixtures/input/simple.fasta -s TTT -o out.fasta --unmatched-out rest.fasta
+    #[test]
+    fn test_extract_unmatched_out() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/simple.fasta")],
+            kmer_seq: Some(vec!["TTT".to_string()]),
+            out_fastx: Some(temp_dir.path().join("out.fasta")),
+            unmatched_out: Some(temp_dir.path().join("rest.fasta")),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        let read = |name: &str| fs::read_to_string(temp_dir.path().join(name)).unwrap();
+        assert_eq!(read("out.fasta"), ">seq2\nTTTTTTTT\n>seq3\nTCACGTTT\n");
+        assert_eq!(read("rest.fasta"), ">seq1\nACGTACGT\n");
+
+        Ok(())
+    }
+
+    // Write errors of the buffered outputs, e.g. on a full disk, are reported
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_extract_write_error() {
+        // Output files linked to /dev/full fail when their buffer is written
+        let temp_dir = tempfile::tempdir().unwrap();
+        let full = temp_dir.path().join("full.fasta");
+        std::os::unix::fs::symlink("/dev/full", &full).unwrap();
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/simple.fasta")],
+            kmer_seq: Some(vec!["TTT".to_string()]),
+            ..test_args()
+        };
+
+        let err = extract_records(CmdExtract { out_fastx: Some(full.clone()), ..args.clone() }).unwrap_err();
+        assert!(format!("{err:#}").contains("Error writing to output file."));
+
+        let err = extract_records(CmdExtract {
+            out_fastx: Some(temp_dir.path().join("out.fasta")),
+            unmatched_out: Some(full),
+            ..args
+        })
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("Error writing to unmatched output file."));
+    }
+
+    // Pairs that are not selected are written to the unmatched output files
+    // Corresponds to: cargo run -- extract -i tests/fixtures/input/paired-1.fastq -2 tests/fixtures/input/paired-2.fastq --pair-mode r1 -s CTT -o out.fastq --unmatched-out rest.fastq
+    #[test]
+    fn test_extract_paired_unmatched_out() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-1.fastq")],
+            in_fastq_2: vec![PathBuf::from("tests/fixtures/input/paired-2.fastq")],
+            pair_mode: PairMode::R1,
+            kmer_seq: Some(vec!["CTT".to_string()]),
+            out_fastx: Some(temp_dir.path().join("out")),
+            unmatched_out: Some(temp_dir.path().join("rest")),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        let read = |name: &str| fs::read_to_string(temp_dir.path().join(name)).unwrap();
+        assert_eq!(read("out_1.fastq"), "@seq1/1\nACTTACGT\n+\nIIIIIIII\n");
+        assert_eq!(read("out_2.fastq"), "@seq1/2\nGCTATAAT\n+\nIIIIIIII\n");
+        assert_eq!(read("rest_1.fastq"), "@seq2/1\nTTTTTTTT\n+\nIIIIIIII\n");
+        assert_eq!(read("rest_2.fastq"), "@seq2/2\nAAACTTCA\n+\nIIIIIIII\n");
+
+        Ok(())
+    }
+
+    // Hits over low-quality bases are rejected and counted separately
+    // Corresponds to: cargo run -- extract -i tests/fixtures/input/low-quality.fastq -s CTT --min-base-qual 20 -o out.fastq -j out.json
+    #[test]
+    fn test_extract_min_base_qual() -> Result<()> {
+        for aho_corasick in [false, true] {
+            let temp_dir = tempfile::tempdir()?;
+            let out_fastq = temp_dir.path().join("out.fastq");
+            let out_json = temp_dir.path().join("out.json");
+            let args = CmdExtract {
+                in_fastx: vec![PathBuf::from("tests/fixtures/input/low-quality.fastq")],
+                kmer_seq: Some(vec!["CTT".to_string()]),
+                out_fastx: Some(out_fastq.clone()),
+                aho_corasick,
+                json_log: Some(out_json.clone()),
+                min_base_qual: Some(20),
+                ..test_args()
+            };
+
+            extract_records(args)?;
+
+            assert_eq!(
+                fs::read_to_string(&out_fastq)?,
+                "@read2\nGGCTTAAA\n+\nIIIIIIII\n"
+            );
+            let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out_json)?)?;
+            assert_eq!(json["summary_statistics"]["number_of_matches"], 1);
+            assert_eq!(json["summary_statistics"]["number_of_hits_rejected_for_quality"], 1);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_record_filters() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let in_fasta = temp_dir.path().join("in.fasta");
+        fs::write(&in_fasta, ">short\nACG\n>ns\nACGNNNNN\n>ok\nACGTTT\n")?;
+        let out_fasta = temp_dir.path().join("out.fasta");
+        let out_json = temp_dir.path().join("out.json");
+        let args = CmdExtract {
+            in_fastx: vec![in_fasta],
+            kmer_seq: Some(vec!["ACG".to_string()]),
+            out_fastx: Some(out_fasta.clone()),
+            json_log: Some(out_json.clone()),
+            min_length: Some(4),
+            max_n_fraction: Some(0.5),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        assert_eq!(fs::read_to_string(&out_fasta)?, ">ok\nACGTTT\n");
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&out_json)?)?;
+        assert_eq!(json["summary_statistics"]["number_of_records_searched"], 1);
+        assert_eq!(json["summary_statistics"]["number_of_records_skipped_by_filters"], 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_mask_and_trim() -> Result<()> {
+        let cases = [
+            (Some(MaskMode::Soft), None, "@read1\nActtACGT\n+\nII##IIII\n@read2\nGGcttAAA\n+\nIIIIIIII\n"),
+            (Some(MaskMode::Hard), None, "@read1\nANNNACGT\n+\nI!!!IIII\n@read2\nGGNNNAAA\n+\nII!!!III\n"),
+            (None, Some(TrimMode::Left), "@read1\nACGT\n+\nIIII\n@read2\nAAA\n+\nIII\n"),
+            (None, Some(TrimMode::Right), "@read1\nA\n+\nI\n@read2\nGG\n+\nII\n"),
+            (None, Some(TrimMode::Hit), "@read1\nAACGT\n+\nIIIII\n@read2\nGGAAA\n+\nIIIII\n"),
+        ];
+        for (mask, trim, expected) in cases {
+            let temp_dir = tempfile::tempdir()?;
+            let out_fastq = temp_dir.path().join("out.fastq");
+            let args = CmdExtract {
+                in_fastx: vec![PathBuf::from("tests/fixtures/input/low-quality.fastq")],
+                kmer_seq: Some(vec!["CTT".to_string()]),
+                out_fastx: Some(out_fastq.clone()),
+                mask,
+                trim,
+                ..test_args()
+            };
+
+            extract_records(args)?;
+
+            assert_eq!(fs::read_to_string(&out_fastq)?, expected, "--mask {mask:?} --trim {trim:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hit_edit_overlapping_hits() {
+        let hits = [(1, 4), (3, 6)];
+        let mut seq = b"ACGTACGT".to_vec();
+        let mut qual = b"ABCDEFGH".to_vec();
+        HitEdit::Trim(TrimMode::Hit).apply(&mut seq, Some(&mut qual), &hits);
+        assert_eq!(seq, b"AGT");
+        assert_eq!(qual, b"AGH");
+
+        let mut seq = b"ACGTACGT".to_vec();
+        HitEdit::Mask(MaskMode::Soft).apply(&mut seq, None, &hits);
+        assert_eq!(seq, b"AcgtacGT");
+    }
+
+    #[test]
+    fn test_extract_flank() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let in_fasta = temp_dir.path().join("contigs.fasta");
+        fs::write(
+            &in_fasta,
+            ">contig1 description\nGGGGGGGGGGACGTGGGGGGGGGGGGGGGGGGGACGTCACGTGGGGGGGGG\n>contig2\nGGGGGGGG\n",
+        )?;
+        let out_fasta = temp_dir.path().join("out.fasta");
+        let args = CmdExtract {
+            in_fastx: vec![in_fasta],
+            kmer_seq: Some(vec!["ACGT".to_string(), "TCAC".to_string()]),
+            out_fastx: Some(out_fasta.clone()),
+            flank: Some(3),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        assert_eq!(
+            fs::read_to_string(&out_fasta)?,
+            ">contig1:8-17 kmers=ACGT\nGGGACGTGGG\n>contig1:31-45 kmers=ACGT,TCAC\nGGGACGTCACGTGGG\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_bed_and_gff_out() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let bed_path = temp_dir.path().join("hits.bed");
+        let gff_path = temp_dir.path().join("hits.gff");
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/simple.fasta")],
+            kmer_seq: Some(vec!["ACG".to_string()]),
+            reverse_complement: true,
+            suppress_output: true,
+            bed_out: Some(bed_path.clone()),
+            gff_out: Some(gff_path.clone()),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        // The reverse complement CGT is reported as ACG on the minus strand
+        assert_eq!(
+            fs::read_to_string(&bed_path)?,
+            "seq1\t0\t3\tACG\t0\t+\n\
+             seq1\t1\t4\tACG\t0\t-\n\
+             seq1\t4\t7\tACG\t0\t+\n\
+             seq1\t5\t8\tACG\t0\t-\n\
+             seq3\t2\t5\tACG\t0\t+\n\
+             seq3\t3\t6\tACG\t0\t-\n"
+        );
+        let gff = fs::read_to_string(&gff_path)?;
+        let gff_lines: Vec<&str> = gff.lines().collect();
+        assert_eq!(gff_lines.len(), 7);
+        assert_eq!(gff_lines[0], "##gff-version 3");
+        assert_eq!(gff_lines[1], "seq1\tMerKurio\tnucleotide_match\t1\t3\t.\t+\t.\tName=ACG;kmer=ACG");
+        assert_eq!(gff_lines[2], "seq1\tMerKurio\tnucleotide_match\t2\t4\t.\t-\t.\tName=ACG;kmer=CGT");
+
+        Ok(())
+    }
+
+    // Corresponds to: cargo run -- extract -i tests/fixtures/input/simple.fasta -s ACG -r -S --hits-parquet hits.parquet
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_extract_hits_parquet() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let table_path = temp_dir.path().join("hits.parquet");
+        let args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/simple.fasta")],
+            kmer_seq: Some(vec!["ACG".to_string()]),
+            reverse_complement: true,
+            suppress_output: true,
+            hits_parquet: Some(table_path.clone()),
+            ..test_args()
+        };
+
+        extract_records(args)?;
+
+        // Typed columns, with the reverse complement CGT on the minus strand
+        let (rows, summary) = crate::hit_table::tests::read_hit_table(&table_path);
+        let hit = |id: &str, pattern: &str, pos, strand: &str| {
+            (id.to_string(), pattern.to_string(), pos, Some(strand.to_string()), None, None)
+        };
+        assert_eq!(
+            rows,
+            [
+                hit("seq1", "ACG", 0, "+"),
+                hit("seq1", "CGT", 1, "-"),
+                hit("seq1", "ACG", 4, "+"),
+                hit("seq1", "CGT", 5, "-"),
+                hit("seq3", "ACG", 2, "+"),
+                hit("seq3", "CGT", 3, "-"),
+            ]
+        );
+        assert_eq!(summary["summary_statistics"]["number_of_matches"], 6);
+        assert_eq!(summary["meta_information"]["subcommand"], "extract");
+
+        Ok(())
+    }
+
+    /// Logs without their header (timestamp and command line), and JSON logs
+    /// without their meta information, of a run with Aho-Corasick or BNDMq.
+    fn logs_of_algorithm(args: &CmdExtract, aho_corasick: bool, dir: &Path) -> Result<(Vec<String>, serde_json::Value)> {
+        let name = if aho_corasick { "ac" } else { "bndmq" };
+        let log_path = dir.join(format!("{name}.log"));
+        let json_path = dir.join(format!("{name}.json"));
+        let mut args = args.clone();
+        args.aho_corasick = aho_corasick;
+        args.q_size = if aho_corasick { None } else { Some(1) };
+        args.out_log = Some(log_path.clone());
+        args.json_log = Some(json_path.clone());
+        extract_records(args)?;
+
+        let log = fs::read_to_string(&log_path)?.lines().skip(4).map(String::from).collect();
+        let mut json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path)?)?;
+        json["meta_information"] = serde_json::Value::Null;
+        Ok((log, json))
+    }
+
+    #[test]
+    fn test_extract_logs_identical_for_both_algorithms() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let base_args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/simple.fasta")],
+            kmer_seq: Some(vec!["ACG".to_string(), "CG".to_string()]),
+            reverse_complement: true,
+            suppress_output: true,
+            ..test_args()
+        };
+        // Overlapping patterns in paired-end reads
+        let paired_args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/paired-1.fastq")],
+            in_fastq_2: vec![PathBuf::from("tests/fixtures/input/paired-2.fastq")],
+            kmer_seq: Some(vec!["TT".to_string(), "TTT".to_string(), "ACG".to_string()]),
+            reverse_complement: false,
+            ..base_args.clone()
+        };
+        // Hits rejected for low base quality
+        let quality_args = CmdExtract {
+            in_fastx: vec![PathBuf::from("tests/fixtures/input/low-quality.fastq")],
+            kmer_seq: Some(vec!["CTT".to_string(), "TA".to_string(), "AAA".to_string()]),
+            min_base_qual: Some(20),
+            ..base_args.clone()
+        };
+
+        for (i, args) in [base_args, paired_args, quality_args].iter().enumerate() {
+            let dir = temp_dir.path().join(i.to_string());
+            fs::create_dir(&dir)?;
+            let (ac_log, ac_json) = logs_of_algorithm(args, true, &dir)?;
+            let (bndmq_log, bndmq_json) = logs_of_algorithm(args, false, &dir)?;
+            assert!(ac_log.iter().any(|line| !line.starts_with('#')), "No hits in case {i}");
+            assert_eq!(ac_log, bndmq_log, "Text logs differ in case {i}");
+            assert_eq!(ac_json, bndmq_json, "JSON logs differ in case {i}");
+        }
+
+        Ok(())
+    }
+
+    // The options can be created from command-line arguments, applying a
+    // configuration file
+    #[test]
+    fn test_extract_from_args() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config = temp_dir.path().join("merkurio.toml");
+        fs::write(&config, "[extract]\nunmatched_out = \"rest.fasta\"\nkmer_seq = [\"ACG\"]\n")?;
+        let out = temp_dir.path().join("out.fasta");
+        let json_log = temp_dir.path().join("log.json");
+        let args = CmdExtract::from_args([
+            "-i".into(),
+            "tests/fixtures/input/simple.fasta".into(),
+            "-s".into(),
+            "TTT".into(),
+            "-o".into(),
+            out.clone().into_os_string(),
+            "-j".into(),
+            json_log.clone().into_os_string(),
+            "--config".into(),
+            config.into_os_string(),
+        ])?;
+        // The k-mers given on the command line override those of the file
+        assert_eq!(args.unmatched_out, Some(PathBuf::from("rest.fasta")));
+        assert_eq!(args.kmer_seq, Some(vec!["TTT".to_string()]));
+        assert_eq!(args.config_values.applied.len(), 1);
+        assert_eq!(args.config_values.overridden.len(), 1);
+
+        let args = CmdExtract { unmatched_out: Some(temp_dir.path().join("rest.fasta")), ..args };
+        extract_records(args)?;
+        assert_eq!(fs::read_to_string(out)?, ">seq2\nTTTTTTTT\n>seq3\nTCACGTTT\n");
+        let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(json_log)?)?;
+        let configuration = &json["meta_information"]["configuration"];
+        assert_eq!(configuration["options"]["unmatched_out"], "rest.fasta");
+        assert_eq!(configuration["overridden_options"]["kmer_seq"], serde_json::json!(["ACG"]));
+
+        // Invalid arguments are reported as errors
+        assert!(CmdExtract::from_args(["-s", "ACG"]).is_err());
+
+        Ok(())
+    }
 }
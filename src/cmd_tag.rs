@@ -6,24 +6,29 @@
 //! automatically based on the input file extension. Additionally, adds a tag to the BAM/SAM
 //! header with the program information.
 
-use aho_corasick::AhoCorasick;
 use anyhow::{Context, Result};
+use bam::header::{EntryType, HeaderLine};
 use bam::record::tags;
 use bam::{RecordReader, RecordWriter};
-use clap::{ArgAction, ArgGroup, Args, crate_name, crate_version};
+use clap::{ArgAction, ArgGroup, Args, ValueEnum, crate_name, crate_version};
 use jiff::{Unit, Zoned};
-use serde_json;
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::str::from_utf8;
+use std::io::{BufWriter, Write};
 use std::{env, fs, io};
 
+use crate::config::{ConfigValues, config_header, config_json, parse_subcommand_args};
 use crate::helpers::{
-    check_log_flag_conflict, error_if_directory, parse_pattern_list, recommend_aho_corasick,
+    QualityFilter, RecordFilter, check_log_flag_conflict, error_if_directory, parse_pattern_list_with_origins,
+    recommend_aho_corasick,
 };
-use crate::logger::{BufferedLogger, JsonLogger};
-use crate::pattern_matching::{BNDMq, tune_q_value};
+use crate::hit_table::{HitLocation, HitTable};
+use crate::logger::{BufferedLogger, JsonFormat, JsonLogger, LogLevel, summary_json};
+use crate::pattern_matching::build_matcher;
+use crate::stats::{PatternStats, RecordSearch, SearchStats};
 
 #[derive(Args)]
 #[clap(group(
@@ -36,7 +41,7 @@ group(
     ArgGroup::new("logging")
         .required(false)
         .multiple(true)
-        .args(&["out_log", "json_log"]),
+        .args(&["out_log", "json_log", "coverage"]),
 ),
 group(
     ArgGroup::new("matching")
@@ -95,14 +100,54 @@ pub struct CmdTag {
     #[clap(short = 'l', long, default_value(None), default_missing_value("STDOUT"), num_args = 0..=1, )]
     out_log: Option<PathBuf>,
 
+    /// Detail of the plain text log: one line per hit, one line per record with its hits, or only the summary statistics.
+    #[clap(long, value_enum, default_value_t = LogLevel::Hit, requires("out_log"))]
+    log_level: LogLevel,
+
     /// Write JSON log to stdout if only the flag is passed, or to a file if a path is provided.
     #[clap(short = 'j', long, default_value(None), default_missing_value("STDOUT"), num_args = 0..=1, )]
     json_log: Option<PathBuf>,
 
+    /// Layout of the JSON log: a single pretty-printed document, or JSON Lines with one hit per line and a final summary line.
+    #[clap(long, value_enum, default_value_t = JsonFormat::Pretty, requires("json_log"))]
+    json_format: JsonFormat,
+
     /// Number of parallel threads to use for processing BAM files.
     #[clap(short = 'p', long, default_value("1"))]
     threads: u16,
 
+    /// Only count hits whose bases all have at least this Phred quality score.
+    #[clap(long)]
+    min_base_qual: Option<u8>,
+
+    /// Only count hits whose bases have at least this mean Phred quality score.
+    #[clap(long)]
+    min_mean_qual: Option<f64>,
+
+    /// Skip records shorter than this length (they are not searched, tagged or counted as matching).
+    #[clap(long)]
+    min_length: Option<usize>,
+
+    /// Skip records longer than this length (they are not searched, tagged or counted as matching).
+    #[clap(long)]
+    max_length: Option<usize>,
+
+    /// Skip records with a higher fraction of 'N' bases than this value between 0 and 1.
+    #[clap(long)]
+    max_n_fraction: Option<f64>,
+
+    /// Skip records with any of these SAM flag bits set (decimal or hexadecimal, e.g. 0x904).
+    #[clap(short = 'F', long, value_parser = parse_sam_flags, default_value("0"))]
+    exclude_flags: u16,
+
+    /// Skip records with a mapping quality below this value.
+    #[clap(long)]
+    min_mapq: Option<u8>,
+
+    /// Skip unmapped records (same as adding 0x4 to --exclude-flags).
+    #[clap(long, action(ArgAction::SetTrue), default_value("false"))]
+    skip_unmapped: bool,
+
     /// Suppress output of found records (no records are written to a file or stdout); use if only matching statistics are of interest.
     #[clap(
         short = 'S',
@@ -122,6 +167,34 @@ pub struct CmdTag {
     #[clap(short = 'v', long, action(ArgAction::SetTrue), default_value("false"))]
     invert_match: bool,
 
+    /// Write the records filtered out with -m or -v to this SAM/BAM file instead of dropping them; file type is inferred from the file extension.
+    #[clap(long, requires("matching"), conflicts_with("suppress_output"))]
+    unmatched_out: Option<PathBuf>,
+
+    /// Keep or drop all records of a template (both mates) together when filtering with -m or -v; a template matches if any of its records matches.
+    #[clap(long, action(ArgAction::SetTrue), default_value("false"), requires("matching"))]
+    keep_mates: bool,
+
+    /// Write the coverage of matching records or k-mer hits along the reference sequences to this bedGraph file; only mapped records are counted.
+    #[clap(long)]
+    coverage: Option<PathBuf>,
+
+    /// Size of the bins for the coverage; each bin counts the records or hits overlapping it.
+    #[clap(long, default_value("1"), requires("coverage"), value_parser = clap::value_parser!(u32).range(1..))]
+    coverage_bin: u32,
+
+    /// Count the matching records (reads) or the k-mer hits (hits) overlapping each reference position or bin.
+    #[clap(long, value_enum, default_value_t = CoverageUnit::Reads, requires("coverage"))]
+    coverage_unit: CoverageUnit,
+
+    /// Write every hit to this Parquet file with typed columns (file, record ID, pattern, position, reference and reference position), e.g. to load the hits into Polars or DuckDB; the summary statistics are stored as file metadata.
+    #[clap(long)]
+    hits_parquet: Option<PathBuf>,
+
+    /// Tag to add to records with the k-mers found in their mates (only with --keep-mates).
+    #[clap(long, default_value("mk"))]
+    mate_tag: String,
+
     /// Use case-insensitive matching.
     #[clap(short = 'I', long, action(ArgAction::SetTrue), default_value("false"))]
     case_insensitive: bool,
@@ -147,6 +220,42 @@ pub struct CmdTag {
         hide_short_help = true
     )]
     aho_corasick: bool,
+
+    /// TOML configuration file with options for the subcommands; options given on the command line take precedence.
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Name of a preset in the configuration file to apply on top of the options for the subcommand.
+    #[clap(long, requires("config"))]
+    preset: Option<String>,
+
+    /// Options applied from the configuration file, set before running the subcommand.
+    #[clap(skip)]
+    pub config_values: ConfigValues,
+}
+
+impl CmdTag {
+    /// Creates the options of the `tag` subcommand from its command-line
+    /// arguments, e.g. `CmdTag::from_args(["-i", "reads.bam", "-s", "ACG", "-o", "out.bam"])`. A configuration file given
+    /// with `--config` is applied as on the command line.
+    pub fn from_args<I, A>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<OsString>,
+    {
+        let (mut cmd, config_values) = parse_subcommand_args::<Self, _, _>("tag", args)?;
+        cmd.config_values = config_values;
+        Ok(cmd)
+    }
+}
+
+/// What is counted for the coverage along the reference sequences.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoverageUnit {
+    /// Aligned span of records with a hit.
+    Reads,
+    /// Aligned span of the k-mer hits.
+    Hits,
 }
 
 /// Core function of the `tag` subcommand that reads a SAM/BAM file, searches
@@ -170,7 +279,7 @@ pub fn tag_records(args: CmdTag) -> Result<()> {
     // Get input filename for logging
     let in_records_filename = args.in_file.file_name().unwrap().to_str().unwrap();
 
-    let pattern_list = parse_pattern_list(
+    let (pattern_list, pattern_origins) = parse_pattern_list_with_origins(
         &args.kmer_file,
         args.kmer_seq,
         args.reverse_complement,
@@ -213,82 +322,22 @@ pub fn tag_records(args: CmdTag) -> Result<()> {
     let out_file = args.out_file.clone();
 
     // Activate logging if a log or JSON log file is provided
-    let logging_active = log_file.is_some() || args.json_log.is_some();
+    // The coverage and the hit table need the positions of all hits, so all
+    // patterns are searched like when logging
+    let logging_active =
+        log_file.is_some() || args.json_log.is_some() || args.coverage.is_some() || args.hits_parquet.is_some();
 
     // Check if number of threads is at least 1
     if args.threads < 1 {
         anyhow::bail!("Number of threads must be at least 1.");
     }
-    // Check if the tag is a valid tag name
-    let tag_validated: [u8; 2] = if args.tag.len() != 2 {
-        anyhow::bail!("Tag must be exactly two characters long.");
-    } else {
-        args.tag
-            .as_bytes()
-            .try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid tag format."))?
-    };
-
-    // Initialize algorithm instances for each pattern. Only construct the Aho-
-    // Corasick automaton when requested.
-    let (ac, bndmq_collection): (Option<AhoCorasick>, Vec<(String, BNDMq)>) = if args.aho_corasick {
-        let ac = AhoCorasick::builder()
-            // Use DFA for better search performance at higher memory cost
-            .kind(Some(aho_corasick::AhoCorasickKind::DFA))
-            .ascii_case_insensitive(args.case_insensitive)
-            .build(pattern_list.clone())
-            .unwrap();
-        (Some(ac), Vec::new())
-    } else {
-        let mut bndmq_collection = Vec::with_capacity(pattern_list.len());
-        for pattern in &pattern_list {
-            // Tune q value for BNDMq based on the pattern length if not provided
-            let q = args
-                .q_size
-                .unwrap_or_else(|| tune_q_value(pattern).unwrap());
-            bndmq_collection.push((pattern.clone(), BNDMq::new(pattern.as_bytes(), q)?));
-        }
-        (None, bndmq_collection)
-    };
+    // Check if the tags are valid tag names
+    let tag_validated = validate_tag(&args.tag)?;
+    let mate_tag_validated = validate_tag(&args.mate_tag)?;
 
-    fn infer_record_writer(
-        threads: u16,
-        out_file: &Option<PathBuf>,
-        extension: &str,
-        header: bam::Header,
-    ) -> Result<Box<dyn RecordWriter>> {
-        match extension {
-            "bam" => {
-                let out_file = out_file
-                    .as_ref()
-                    .ok_or_else(|| anyhow::anyhow!("Output file not provided for BAM writing."))?;
-                let path = out_file.with_extension(extension);
-                Ok(Box::new(
-                    bam::bam_writer::BamWriterBuilder::new()
-                        .additional_threads(threads - 1)
-                        .from_path(&path, header)
-                        .with_context(|| format!("Error writing BAM file: {}", path.display()))?,
-                ))
-            }
-            "sam" => {
-                let out_file = out_file
-                    .as_ref()
-                    .ok_or_else(|| anyhow::anyhow!("Output file not provided for SAM writing."))?;
-                let path = out_file.with_extension(extension);
-                Ok(Box::new(
-                    bam::sam::SamWriterBuilder::new()
-                        .from_path(&path, header)
-                        .with_context(|| format!("Error writing SAM file: {}", path.display()))?,
-                ))
-            }
-            "STDOUT" => Ok(Box::new(
-                bam::sam::SamWriterBuilder::new()
-                    .from_stream(io::stdout(), header)
-                    .with_context(|| "Error writing SAM file to stdout.")?,
-            )),
-            _ => anyhow::bail!("Invalid output file type. Must be BAM or SAM file."),
-        }
-    }
+    // Initialize the matcher. Only construct the Aho-Corasick automaton when
+    // requested.
+    let matcher = build_matcher(&pattern_list, args.aho_corasick, args.case_insensitive, args.q_size)?;
 
     // Check if file is a BAM or SAM file and open it for reading
     let in_file_extension = args
@@ -308,7 +357,7 @@ pub fn tag_records(args: CmdTag) -> Result<()> {
     };
 
     // Initialize buffered logger with 8KB buffer
-    let mut logger = BufferedLogger::new(log_file, 8192);
+    let mut logger = BufferedLogger::with_level(log_file, 8192, args.log_level);
 
     // Initialize JSON logger if requested
     let mut json_logger = if let Some(json_path) = args.json_log.clone() {
@@ -319,29 +368,45 @@ pub fn tag_records(args: CmdTag) -> Result<()> {
                 format!("Error creating JSON log file: {}", json_path.display())
             })?)
         };
-        Some(JsonLogger::new(Some(writer), 8192))
+        Some(JsonLogger::with_format(Some(writer), 8192, args.json_format)?)
     } else {
         None
     };
 
+    // Initialize the hit table if requested
+    let mut hit_table = match &args.hits_parquet {
+        Some(path) => Some(
+            HitTable::create(path)
+                .with_context(|| format!("Problem creating hit table: {}", path.display()))?,
+        ),
+        None => None,
+    };
+
     // Log the list of patterns and header line
     if logging_active {
         // Write header section
-        logger.write_header("#SeqKatcher tag log\n");
-        logger.write_header(&format!("#{}\n", Zoned::now().round(Unit::Second)?));
+        logger.write_header("#SeqKatcher tag log\n")?;
+        logger.write_header(&format!("#{}\n", Zoned::now().round(Unit::Second)?))?;
         logger.write_header(&format!(
             "#Running {} version {}\n",
             crate_name!(),
             crate_version!()
-        ));
+        ))?;
         logger.write_header(&format!(
             "#Command line: {}\n",
             env::args().collect::<Vec<String>>().join(" ")
-        ));
+        ))?;
+        logger.write_header(&config_header(args.config.as_deref(), args.preset.as_deref(), &args.config_values))?;
         logger.write_header(&format!(
             "#Tag used for labeling records: {}\n",
             from_utf8(&tag_validated).unwrap()
-        ));
+        ))?;
+        if args.keep_mates {
+            logger.write_header(&format!(
+                "#Keeping mates together, tag used for k-mers in mates: {}\n",
+                from_utf8(&mate_tag_validated).unwrap()
+            ))?;
+        }
         logger.write_header(&format!(
             "#Searching for {} pattern{} {}\n",
             pattern_list.len(),
@@ -351,312 +416,298 @@ pub fn tag_records(args: CmdTag) -> Result<()> {
             } else {
                 ""
             }
-        ));
-        logger.write_header("#\n#File\tRecord\tPattern\tPosition (zero-based)\n");
-        logger.flush(); // Ensure header is written before records
+        ))?;
+        logger.write_column_header()?;
+        logger.flush()?; // Ensure header is written before records
     }
 
-    // Initialize counters for logging information
-    let mut nb_records_tot = 0;
-    let mut nb_bases: usize = 0;
-    let mut nb_hits_tot = 0;
-    let mut nb_records_hit = 0;
-    let mut pattern_hit_counts = vec![0u32; pattern_list.len()];
-
-    /// Process a single record, updating statistics and writing to output
-    fn process_record(
-        record: &mut bam::Record,
-        ac: Option<&AhoCorasick>,
-        bndmq_collection: &[(String, BNDMq)],
-        pattern_list: &[String],
-        tag_validated: [u8; 2],
-        logging_active: bool,
-        logger: &mut BufferedLogger,
-        in_records_filename: &str,
-        pattern_hit_counts: &mut [u32],
-        nb_hits_tot: &mut usize,
-        nb_records_hit: &mut usize,
-        nb_records_tot: &mut usize,
-        nb_bases: &mut usize,
-        filter_matching: bool,
-        suppress_output: bool,
-        invert_match: bool,
-        writer: &mut Box<dyn RecordWriter>,
-        json_logger: &mut Option<JsonLogger>,
-    ) -> Result<()> {
-        let mut kmers_found = Vec::new();
-
-        let use_ac = ac.is_some();
-
-        // Get occurrences of patterns in the sequence using Aho-Corasick or BNDMq
-        if use_ac {
-            for mat in ac
-                .unwrap()
-                .find_overlapping_iter(&record.sequence().to_vec())
-            {
-                if let Some(pattern) = pattern_list.get(mat.pattern().as_usize()) {
-                    kmers_found.push(pattern.clone());
-                    // Log match information
-                    if logging_active {
-                        *nb_hits_tot += 1;
-
-                        if let Some(count) = pattern_hit_counts.get_mut(mat.pattern().as_usize()) {
-                            *count += 1;
-                        }
-                        logger.log_fields(in_records_filename, record.name(), pattern, mat.start());
-                        if let Some(jl) = json_logger.as_mut() {
-                            jl.log_fields(in_records_filename, record.name(), pattern, mat.start());
-                        }
-                    }
-                } else {
-                    anyhow::bail!("Error retrieving matching pattern by index.")
-                }
-            }
-        } else {
-            // If logging active, search for matching positions and print them
-            if logging_active {
-                for (idx, (pattern, bndmq)) in bndmq_collection.iter().enumerate() {
-                    let mut found_any = false;
-                    for o in bndmq.find_iter(&record.sequence().to_vec()) {
-                        found_any = true;
-                        logger.log_fields(in_records_filename, record.name(), pattern, o);
-                        if let Some(jl) = json_logger.as_mut() {
-                            jl.log_fields(in_records_filename, record.name(), pattern, o);
-                        }
-                        *nb_hits_tot += 1;
-                    }
-                    if found_any {
-                        kmers_found.push(pattern.clone());
-                        if let Some(count) = pattern_hit_counts.get_mut(idx) {
-                            *count += 1;
-                        }
-                    }
-                }
-            // If logging disabled, only search for a match and break if found
-            } else {
-                for (pattern, bndmq) in bndmq_collection {
-                    if bndmq.find_match(&record.sequence().to_vec()) {
-                        kmers_found.push(pattern.clone());
-                    }
-                }
+    // Build the @PG header line with program information
+    let command_line = env::args().collect::<Vec<String>>().join(" ");
+    let mut program_header_line = format!("@PG\tID:{0}\tPN:{0}\tCL:", crate_name!());
+    program_header_line.push_str(&command_line);
+    program_header_line.push_str(format!("\tVN:{}", crate_version!()).as_str());
+
+    // Open the input file and get its header
+    let (mut reader, in_header) =
+        open_record_reader(&args.in_file, in_file_extension, args.threads)?;
+    let mut header = in_header.clone();
+    header.push_line(&program_header_line).unwrap();
+    // Use empty header if suppress_output is set
+    if args.suppress_output {
+        header = bam::Header::new();
+    }
+    // Open the file for records that are filtered out, if requested
+    let mut unmatched_writer = match &args.unmatched_out {
+        Some(path) => {
+            let extension = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or(in_file_extension);
+            if !matches!(extension, "bam" | "sam") {
+                anyhow::bail!("Output file for unmatched records must be a BAM or SAM file.");
             }
+            Some(
+                infer_record_writer(args.threads, &args.unmatched_out, extension, header.clone())
+                    .with_context(|| "Could not create writer for unmatched records.")?,
+            )
         }
-
-        if logging_active {
-            *nb_records_tot += 1;
-            *nb_bases += record.query_len() as usize;
-            if !kmers_found.is_empty() {
-                *nb_records_hit += 1;
-            }
+        None => None,
+    };
+    // Open file for writing with inferred writer
+    let mut writer = match out_file_extension {
+        "bam" | "sam" | "STDOUT" => {
+            infer_record_writer(args.threads, &out_file, out_file_extension, header)
         }
-
-        // Skip record based on matching criteria:
-        // - With filter_matching (-m): keep only records that match
-        // - With invert_match (-v): keep only records that don't match
-        // - Without either: keep all records
-        let should_keep = if filter_matching {
-            !kmers_found.is_empty() // Keep only matching records
-        } else if invert_match {
-            kmers_found.is_empty() // Keep only non-matching records
+        _ => anyhow::bail!("Output file must be a BAM or SAM file."),
+    }
+    .with_context(|| "Could not create writer.")?;
+
+    let mut searcher = RecordSearcher {
+        search: RecordSearch {
+            matcher: matcher.as_ref(),
+            quality_filter: QualityFilter {
+                min_base_qual: args.min_base_qual,
+                min_mean_qual: args.min_mean_qual,
+            },
+            record_filter: RecordFilter {
+                min_length: args.min_length,
+                max_length: args.max_length,
+                max_n_fraction: args.max_n_fraction,
+            },
+            // Qualities of BAM/SAM records have no offset
+            qual_offset: 0,
+        },
+        pattern_list: &pattern_list,
+        logging_active,
+        logger: &mut logger,
+        json_logger: &mut json_logger,
+        hit_table: &mut hit_table,
+        in_header: &in_header,
+        in_records_filename,
+        // Count the hits of the k-mers per strand if their reverse
+        // complements are searched
+        stats: SearchStats::new(if args.reverse_complement {
+            PatternStats::with_origins(&pattern_origins)
         } else {
-            true // Keep all records
-        };
-
-        if !should_keep {
-            return Ok(());
-        }
-
-        // Tag record with presence of k-mers
-        match record.tags().get(&tag_validated) {
-            // Do nothing if tag is empty
-            Some(tags::TagValue::String([], _)) => (),
-            // Otherwise, append the new k-mers to the newly found k-mers
-            Some(tags::TagValue::String(val, _)) => {
-                let s =
-                    from_utf8(val).with_context(|| "Error reading existing tag value as UTF-8")?;
-                kmers_found.extend(s.split(',').map(String::from));
+            PatternStats::new(pattern_list.len())
+        }),
+        exclude_flags: args.exclude_flags | if args.skip_unmapped { UNMAPPED_FLAG } else { 0 },
+        min_mapq: args.min_mapq,
+        coverage: args
+            .coverage
+            .as_ref()
+            .map(|_| HitCoverage::new(in_header.n_references(), args.coverage_bin, args.coverage_unit)),
+    };
+    let file_type = in_file_extension.to_uppercase();
+
+    // Without mates, every record is processed on its own
+    if !args.keep_mates {
+        let mut record = bam::Record::new();
+        while reader
+            .read_into(&mut record)
+            .with_context(|| format!("Error during {file_type} record parsing."))?
+        {
+            // Skipped records (None) are not tagged and count as non-matching
+            let kmers_found = searcher.find_kmers(&record, true)?;
+            let is_match = kmers_found.as_ref().is_some_and(|kmers| !kmers.is_empty());
+
+            // Skip record based on matching criteria:
+            // - With filter_matching (-m): keep only records that match
+            // - With invert_match (-v): keep only records that don't match
+            // - Without either: keep all records
+            let should_keep = if args.filter_matching {
+                is_match // Keep only matching records
+            } else if args.invert_match {
+                !is_match // Keep only non-matching records
+            } else {
+                true // Keep all records
+            };
+            if !should_keep && unmatched_writer.is_none() {
+                continue;
             }
-            None => (),
-            _ => anyhow::bail!("Invalid tag value format. Expected string value."),
-        };
-
-        // Sort and deduplicate k-mers
-        kmers_found.sort_unstable();
-        kmers_found.dedup();
-
-        // Update record with new k-mers
-        record
-            .tags_mut()
-            .push_string(&tag_validated, kmers_found.join(",").as_bytes());
-
-        // Write record to output file if not suppressed
-        if !suppress_output {
-            writer
-                .write(record)
-                .with_context(|| "Error writing record to output file")?;
-        }
 
-        Ok(())
-    }
-
-    // Process records based on file type
-    match in_file_extension {
-        "bam" => {
-            // Open BAM file for reading with x additional threads for decompression
-            let mut reader = bam::BamReader::from_path(&args.in_file, &args.threads - 1)
-                .with_context(|| format!("Error reading BAM file: {:?}", &args.in_file))?;
-            // Get header from BAM file and add program information
-            let command_line = env::args().collect::<Vec<String>>().join(" ");
-            let mut program_header_line = format!("@PG\tID:{0}\tPN:{0}\tCL:", crate_name!());
-            program_header_line.push_str(&command_line);
-            program_header_line.push_str(format!("\tVN:{}", crate_version!()).as_str());
-            let mut header = reader.header().clone();
-            header.push_line(&program_header_line).unwrap();
-            // Use empty header if suppress_output is set
-            if args.suppress_output {
-                header = bam::Header::new();
+            if let Some(kmers_found) = kmers_found {
+                tag_record(&mut record, &tag_validated, kmers_found)?;
             }
-            // Open file for writing with inferred writer
-            let mut writer = match out_file_extension {
-                "bam" | "sam" | "STDOUT" => {
-                    infer_record_writer(args.threads, &out_file, out_file_extension, header)
+            if !should_keep {
+                if let Some(unmatched_writer) = unmatched_writer.as_mut() {
+                    unmatched_writer
+                        .write(&record)
+                        .with_context(|| "Error writing record to unmatched output file")?;
                 }
-                _ => anyhow::bail!("Output file must be a BAM or SAM file."),
+            } else if !args.suppress_output {
+                writer
+                    .write(&record)
+                    .with_context(|| "Error writing record to output file")?;
             }
-            .with_context(|| "Could not create writer.")?;
-
-            // Iterate over BAM records and process them
-            let mut record = bam::Record::new();
-            loop {
-                match reader.read_into(&mut record) {
-                    Ok(true) => {
-                        process_record(
-                            &mut record,
-                            ac.as_ref(),
-                            &bndmq_collection,
-                            &pattern_list,
-                            tag_validated,
-                            logging_active,
-                            &mut logger,
-                            in_records_filename,
-                            &mut pattern_hit_counts,
-                            &mut nb_hits_tot,
-                            &mut nb_records_hit,
-                            &mut nb_records_tot,
-                            &mut nb_bases,
-                            args.filter_matching,
-                            args.suppress_output,
-                            args.invert_match,
-                            &mut writer,
-                            &mut json_logger,
-                        )?;
+        }
+    // Name-collated input: all records of a template are adjacent, so they
+    // can be collected and written (or dropped) together while streaming
+    } else if is_name_collated(&in_header) {
+        let mut template: Vec<(bam::Record, Option<Vec<String>>)> = Vec::new();
+        let mut record = bam::Record::new();
+        loop {
+            let more = reader
+                .read_into(&mut record)
+                .with_context(|| format!("Error during {file_type} record parsing."))?;
+            if !template.is_empty() && (!more || template[0].0.name() != record.name()) {
+                let template_hit = template
+                    .iter()
+                    .any(|(_, kmers)| kmers.as_ref().is_some_and(|kmers| !kmers.is_empty()));
+                let keep = template_hit == args.filter_matching;
+                if keep || unmatched_writer.is_some() {
+                    let segments: Vec<(u16, Vec<String>)> = template
+                        .iter()
+                        .map(|(r, kmers)| (r.flag().0 & SEGMENT_FLAGS, kmers.clone().unwrap_or_default()))
+                        .collect();
+                    for (mut r, kmers) in template.drain(..) {
+                        if let Some(kmers) = kmers {
+                            let mate_kmers = mate_kmers(&segments, r.flag().0 & SEGMENT_FLAGS);
+                            tag_record(&mut r, &tag_validated, kmers)?;
+                            tag_record(&mut r, &mate_tag_validated, mate_kmers)?;
+                        }
+                        if !keep {
+                            if let Some(unmatched_writer) = unmatched_writer.as_mut() {
+                                unmatched_writer.write(&r).with_context(|| {
+                                    "Error writing record to unmatched output file"
+                                })?;
+                            }
+                        } else if !args.suppress_output {
+                            writer
+                                .write(&r)
+                                .with_context(|| "Error writing record to output file")?;
+                        }
                     }
-                    Ok(false) => break,
-                    Err(e) => anyhow::bail!("Error during BAM record parsing: {}", e),
                 }
+                template.clear();
+            }
+            if !more {
+                break;
             }
+            let kmers_found = searcher.find_kmers(&record, true)?;
+            template.push((std::mem::replace(&mut record, bam::Record::new()), kmers_found));
         }
-        "sam" => {
-            // Open SAM file for reading
-            let mut reader = bam::SamReader::from_path(&args.in_file)
-                .with_context(|| format!("Error reading SAM file: {:?}", &args.in_file))?;
-            // Get header from SAM file and add program information
-            let command_line = env::args().collect::<Vec<String>>().join(" ");
-            let mut program_header_line = format!("@PG\tID:{0}\tPN:{0}\tCL:", crate_name!());
-            program_header_line.push_str(&command_line);
-            program_header_line.push_str(format!("\tVN:{}", crate_version!()).as_str());
-            let mut header = reader.header().clone();
-            header.push_line(&program_header_line).unwrap();
-            // Use empty header if suppress_output is set
-            if args.suppress_output {
-                header = bam::Header::new();
+    // Otherwise, the names of matching templates are collected in a first
+    // pass, and their records are written in a second pass
+    } else {
+        let mut matching_templates: HashMap<Vec<u8>, Vec<(u16, Vec<String>)>> = HashMap::new();
+        let mut record = bam::Record::new();
+        while reader
+            .read_into(&mut record)
+            .with_context(|| format!("Error during {file_type} record parsing."))?
+        {
+            if let Some(kmers_found) = searcher.find_kmers(&record, true)?
+                && !kmers_found.is_empty()
+            {
+                matching_templates
+                    .entry(record.name().to_vec())
+                    .or_default()
+                    .push((record.flag().0 & SEGMENT_FLAGS, kmers_found));
             }
-            // Open file for writing with inferred writer
-            let mut writer = match out_file_extension {
-                "bam" | "sam" | "STDOUT" => {
-                    infer_record_writer(args.threads, &out_file, out_file_extension, header)
-                }
-                _ => anyhow::bail!("Output file must be a BAM or SAM file."),
+        }
+
+        let (mut reader, _) = open_record_reader(&args.in_file, in_file_extension, args.threads)?;
+        while reader
+            .read_into(&mut record)
+            .with_context(|| format!("Error during {file_type} record parsing."))?
+        {
+            let segments = matching_templates.get(record.name());
+            let keep = segments.is_some() == args.filter_matching;
+            if !keep && unmatched_writer.is_none() {
+                continue;
             }
-            .with_context(|| "Could not create writer.")?;
-
-            // Iterate over SAM records and process them
-            let mut record = bam::Record::new();
-            loop {
-                match reader.read_into(&mut record) {
-                    Ok(true) => {
-                        process_record(
-                            &mut record,
-                            ac.as_ref(),
-                            &bndmq_collection,
-                            &pattern_list,
-                            tag_validated,
-                            logging_active,
-                            &mut logger,
-                            in_records_filename,
-                            &mut pattern_hit_counts,
-                            &mut nb_hits_tot,
-                            &mut nb_records_hit,
-                            &mut nb_records_tot,
-                            &mut nb_bases,
-                            args.filter_matching,
-                            args.suppress_output,
-                            args.invert_match,
-                            &mut writer,
-                            &mut json_logger,
-                        )?;
-                    }
-                    Ok(false) => break,
-                    Err(e) => anyhow::bail!("Error during SAM record parsing: {}", e),
+            if let Some(kmers_found) = searcher.find_kmers(&record, false)? {
+                let mate_kmers = segments
+                    .map(|segments| mate_kmers(segments, record.flag().0 & SEGMENT_FLAGS))
+                    .unwrap_or_default();
+                tag_record(&mut record, &tag_validated, kmers_found)?;
+                tag_record(&mut record, &mate_tag_validated, mate_kmers)?;
+            }
+            if !keep {
+                if let Some(unmatched_writer) = unmatched_writer.as_mut() {
+                    unmatched_writer
+                        .write(&record)
+                        .with_context(|| "Error writing record to unmatched output file")?;
                 }
+            } else if !args.suppress_output {
+                writer
+                    .write(&record)
+                    .with_context(|| "Error writing record to output file")?;
             }
         }
-        _ => anyhow::bail!("Input file must be a BAM or SAM file."),
     }
 
+    let RecordSearcher {
+        search: RecordSearch { quality_filter, record_filter, .. },
+        stats,
+        exclude_flags,
+        min_mapq,
+        coverage,
+        ..
+    } = searcher;
+    let SearchStats {
+        nb_records_tot,
+        nb_bases,
+        nb_hits_tot,
+        nb_records_hit,
+        nb_hits_rejected,
+        nb_records_skipped,
+        ref patterns,
+    } = stats;
+
+    if let (Some(path), Some(coverage)) = (&args.coverage, coverage) {
+        coverage
+            .write_bedgraph(path, &in_header)
+            .with_context(|| format!("Problem writing coverage file: {}", path.display()))?;
+    }
+    let skip_filter_active = record_filter.is_active() || exclude_flags != 0 || min_mapq.is_some();
+
     // Log summary statistics
     if logging_active {
-        logger.flush();
-        let nb_patterns_found = pattern_hit_counts
-            .iter()
-            .filter(|&&count| count > 0)
-            .count();
+        logger.flush()?;
+        let nb_patterns_found = patterns.nb_patterns_found();
         let nb_patterns_found_percentage =
-            nb_patterns_found as f64 / pattern_hit_counts.len() as f64 * 100.0;
+            nb_patterns_found as f64 / pattern_list.len() as f64 * 100.0;
         logger.write_header(&format!(
             "#\n#Number of patterns found: {}/{} ({:.2} %)\n",
             nb_patterns_found,
-            pattern_hit_counts.len(),
+            pattern_list.len(),
             nb_patterns_found_percentage,
-        ));
-        logger.write_header("#Pattern\tCount\n");
-        for (pattern, count) in pattern_list.iter().zip(pattern_hit_counts.iter()) {
-            logger.write_header(&format!("#{pattern}\t{count}\n"));
+        ))?;
+        logger.write_header("#Pattern\tCount\n")?;
+        for (pattern, count) in pattern_list.iter().zip(patterns.occurrences.iter()) {
+            logger.write_header(&format!("#{pattern}\t{count}\n"))?;
         }
         logger.write_header(&format!(
             "#\n#Total number of records searched: {nb_records_tot}\n"
-        ));
+        ))?;
         logger.write_header(&format!(
             "#Total number of characters searched: {nb_bases}\n"
-        ));
-        logger.write_header(&format!("#Total number of hits: {nb_hits_tot}\n"));
+        ))?;
+        logger.write_header(&format!("#Total number of hits: {nb_hits_tot}\n"))?;
         logger.write_header(&format!(
             "#Number of distinct records with a hit: {nb_records_hit}\n"
-        ));
-        logger.flush();
+        ))?;
+        if quality_filter.is_active() {
+            logger.write_header(&format!(
+                "#Number of hits rejected for low base quality: {nb_hits_rejected}\n"
+            ))?;
+        }
+        if skip_filter_active {
+            logger.write_header(&format!(
+                "#Number of records skipped by filters: {nb_records_skipped}\n"
+            ))?;
+        }
+        logger.write_header(&patterns.to_text(&pattern_list, false))?;
+        logger.flush()?;
     }
 
-    // Finalize JSON log if active
-    if let Some(jl) = json_logger {
+    // Finalize JSON log and hit table if active
+    if json_logger.is_some() || hit_table.is_some() {
         let input_files_json = serde_json::json!({
             "kmer_file": args.kmer_file.as_ref().map(|p| p.to_string_lossy().to_string()),
             "record_file_1": in_records_filename,
         });
-        let pattern_hit_counts_map: HashMap<String, u32> = pattern_list
-            .iter()
-            .cloned()
-            .zip(pattern_hit_counts.iter().copied())
-            .collect();
         let meta_information = serde_json::json!({
             "program": crate_name!(),
             "version": crate_version!(),
@@ -667,27 +718,357 @@ pub fn tag_records(args: CmdTag) -> Result<()> {
             "inverted_matching": args.invert_match,
             "case_insensitive": args.case_insensitive,
             "input_files": input_files_json,
+            "configuration": config_json(args.config.as_deref(), args.preset.as_deref(), &args.config_values),
             "tag": from_utf8(&tag_validated).unwrap(),
+            "keep_mates": args.keep_mates,
+            "mate_tag": if args.keep_mates { Some(from_utf8(&mate_tag_validated).unwrap()) } else { None },
         });
-        let summary_statistics = serde_json::json!({
-            "number_of_patterns_searched": pattern_list.len(),
-            "number_of_patterns_found": pattern_hit_counts.iter().filter(|&&count| count > 0).count(),
-            "number_of_records_searched": nb_records_tot,
-            "number_of_characters_searched": nb_bases,
-            "number_of_matches": nb_hits_tot,
-            "number_of_distinct_records_with_a_hit": nb_records_hit,
-        });
-        jl.finalize(
-            &meta_information,
-            &serde_json::json!(pattern_hit_counts_map),
-            &summary_statistics,
-            None,
-        );
+        let summary_statistics = stats.summary_json(&pattern_list, quality_filter.is_active(), skip_filter_active, false);
+        if let Some(table) = hit_table {
+            table.finalize(&summary_json(
+                &meta_information,
+                &patterns.occurrences_json(&pattern_list),
+                &summary_statistics,
+                None,
+                None,
+            ))?;
+        }
+        if let Some(jl) = json_logger {
+            jl.finalize(
+                &meta_information,
+                &patterns.occurrences_json(&pattern_list),
+                &summary_statistics,
+                None,
+                None,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Mask for the SAM flags identifying the segment of a template (first or
+/// last segment), used to tell mates apart from supplementary alignments.
+const SEGMENT_FLAGS: u16 = 0x40 | 0x80;
+
+/// SAM flag of unmapped records.
+const UNMAPPED_FLAG: u16 = 0x4;
+
+/// Parses SAM flags given as decimal or hexadecimal (prefixed with 0x) number.
+fn parse_sam_flags(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse::<u16>(),
+    };
+    parsed.map_err(|_| format!("Invalid SAM flags '{s}'; expected a decimal or hexadecimal number."))
+}
+
+/// Searches records for patterns, logs the matches and collects statistics.
+struct RecordSearcher<'a> {
+    search: RecordSearch<'a>,
+    pattern_list: &'a [String],
+    logging_active: bool,
+    logger: &'a mut BufferedLogger,
+    json_logger: &'a mut Option<JsonLogger>,
+    hit_table: &'a mut Option<HitTable>,
+    in_header: &'a bam::Header,
+    in_records_filename: &'a str,
+    stats: SearchStats,
+    exclude_flags: u16,
+    min_mapq: Option<u8>,
+    coverage: Option<HitCoverage>,
+}
+
+impl RecordSearcher<'_> {
+    /// Returns the patterns found in a record, or `None` if the record is
+    /// skipped by the pre-filters. Matches are logged and counted only if
+    /// `log_matches` is set, so records can be searched again without
+    /// affecting the statistics.
+    fn find_kmers(&mut self, record: &bam::Record, log_matches: bool) -> Result<Option<Vec<String>>> {
+        let mut kmers_found = Vec::new();
+        let logging_active = self.logging_active && log_matches;
+        // Hits as (position, length) for the coverage
+        let mut hits: Vec<(usize, usize)> = Vec::new();
+        let sequence = record.sequence().to_vec();
+
+        if record.flag().0 & self.exclude_flags != 0 || self.min_mapq.is_some_and(|min| record.mapq() < min) {
+            if logging_active {
+                self.stats.nb_records_skipped += 1;
+            }
+            return Ok(None);
+        }
+        // Missing qualities are stored as 0xFF
+        let qual = Some(record.qualities().raw()).filter(|q| q.first().is_some_and(|&q| q != 0xFF));
+
+        // If logging active, find all hits and report them in the same order
+        // for both algorithms
+        if logging_active {
+            let mut found = Vec::new();
+            let Some(record_nb) = self.search.search(&mut self.stats, &sequence, qual, None, &mut found) else {
+                return Ok(None);
+            };
+            for (pos, idx) in found {
+                let pattern = &self.pattern_list[idx];
+                self.logger.log_hit(self.in_records_filename, record.name(), record_nb, pattern, pos)?;
+                if let Some(jl) = self.json_logger.as_mut() {
+                    jl.log_fields(self.in_records_filename, record.name(), pattern, pos)?;
+                }
+                if let Some(table) = self.hit_table.as_mut() {
+                    let location = reference_location(self.in_header, record, pos, pattern.len());
+                    table.log_hit(self.in_records_filename, record.name(), pattern, pos, location)?;
+                }
+                hits.push((pos, pattern.len()));
+                kmers_found.push(pattern.clone());
+            }
+            if !kmers_found.is_empty()
+                && let Some(coverage) = self.coverage.as_mut()
+            {
+                coverage.add_record(record, &hits);
+            }
+        // If logging disabled, only collect each pattern found once
+        } else if self.search.record_filter.passes(&sequence) {
+            let mut seen = vec![false; self.pattern_list.len()];
+            for (idx, start, end) in self.search.matcher.find_iter(&sequence) {
+                if !seen[idx] && self.search.quality_filter.passes(qual, 0, start, end - start) {
+                    seen[idx] = true;
+                    kmers_found.push(self.pattern_list[idx].clone());
+                }
+            }
+        } else {
+            return Ok(None);
+        }
+
+        Ok(Some(kmers_found))
+    }
+}
+
+/// Reference sequence and position of the first aligned base of a hit given
+/// as (query position, length), for the hit table. Unmapped records and hits
+/// without aligned bases (e.g. in soft-clipped bases) have no location.
+fn reference_location<'h>(header: &'h bam::Header, record: &bam::Record, pos: usize, len: usize) -> HitLocation<'h> {
+    if !record.flag().is_mapped() || record.ref_id() < 0 || record.start() < 0 {
+        return HitLocation::default();
+    }
+    let reference_position = record
+        .cigar()
+        .matching_pairs(record.start() as u32)
+        .filter(|&(q_pos, _)| (pos..pos + len).contains(&(q_pos as usize)))
+        .map(|(_, r_pos)| r_pos as u64)
+        .min();
+    HitLocation {
+        strand: None,
+        reference: header.reference_name(record.ref_id() as u32),
+        reference_position,
+    }
+}
+
+/// Coverage of matching records or k-mer hits along the reference
+/// sequences, stored as changes of the coverage at the start and end of
+/// each interval, per reference sequence and bin.
+struct HitCoverage {
+    deltas: Vec<BTreeMap<u32, i64>>,
+    bin_size: u32,
+    unit: CoverageUnit,
+}
+
+impl HitCoverage {
+    fn new(nb_references: usize, bin_size: u32, unit: CoverageUnit) -> Self {
+        Self {
+            deltas: vec![BTreeMap::new(); nb_references],
+            bin_size,
+            unit,
+        }
+    }
+
+    /// Add the aligned span of a matching record, or of each of its hits
+    /// given as (query position, length). Unmapped records are ignored.
+    fn add_record(&mut self, record: &bam::Record, hits: &[(usize, usize)]) {
+        if !record.flag().is_mapped() || record.ref_id() < 0 || record.start() < 0 {
+            return;
+        }
+        let ref_id = record.ref_id() as usize;
+        match self.unit {
+            CoverageUnit::Reads => {
+                self.add_interval(ref_id, record.start() as u32, record.calculate_end() as u32);
+            }
+            CoverageUnit::Hits => {
+                for &(pos, len) in hits {
+                    // Reference positions of the aligned bases of the hit
+                    let ref_positions = record
+                        .cigar()
+                        .matching_pairs(record.start() as u32)
+                        .filter(|&(q_pos, _)| (pos..pos + len).contains(&(q_pos as usize)))
+                        .map(|(_, r_pos)| r_pos);
+                    if let Some((start, end)) = ref_positions.fold(None, |span, r_pos| match span {
+                        None => Some((r_pos, r_pos + 1)),
+                        Some((start, end)) => Some((start.min(r_pos), end.max(r_pos + 1))),
+                    }) {
+                        self.add_interval(ref_id, start, end);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Count an interval `[start, end)` for all bins it overlaps.
+    fn add_interval(&mut self, ref_id: usize, start: u32, end: u32) {
+        if end <= start || ref_id >= self.deltas.len() {
+            return;
+        }
+        let deltas = &mut self.deltas[ref_id];
+        *deltas.entry(start / self.bin_size).or_default() += 1;
+        *deltas.entry((end - 1) / self.bin_size + 1).or_default() -= 1;
     }
 
+    /// Write the coverage as bedGraph, merging adjacent bins with the same
+    /// value and omitting regions without coverage.
+    fn write_bedgraph(&self, path: &Path, header: &bam::Header) -> Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(path)?);
+        for (ref_id, deltas) in self.deltas.iter().enumerate() {
+            let name = header.reference_name(ref_id as u32).unwrap_or_default();
+            let ref_len = header.reference_len(ref_id as u32).unwrap_or(u32::MAX) as u64;
+            let mut value = 0i64;
+            let mut start_bin = 0u32;
+            for (&bin, &delta) in deltas {
+                if delta == 0 {
+                    continue;
+                }
+                if value != 0 {
+                    let start = start_bin as u64 * self.bin_size as u64;
+                    let end = (bin as u64 * self.bin_size as u64).min(ref_len);
+                    if start < end {
+                        writeln!(writer, "{name}\t{start}\t{end}\t{value}")?;
+                    }
+                }
+                value += delta;
+                start_bin = bin;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Tag record with presence of k-mers, appending them to existing values of
+/// the tag. The k-mers are sorted and deduplicated.
+fn tag_record(record: &mut bam::Record, tag: &[u8; 2], mut kmers_found: Vec<String>) -> Result<()> {
+    match record.tags().get(tag) {
+        // Do nothing if tag is empty
+        Some(tags::TagValue::String([], _)) => (),
+        // Otherwise, append the new k-mers to the newly found k-mers
+        Some(tags::TagValue::String(val, _)) => {
+            let s = from_utf8(val).with_context(|| "Error reading existing tag value as UTF-8")?;
+            kmers_found.extend(s.split(',').map(String::from));
+        }
+        None => (),
+        _ => anyhow::bail!("Invalid tag value format. Expected string value."),
+    };
+
+    // Sort and deduplicate k-mers
+    kmers_found.sort_unstable();
+    kmers_found.dedup();
+
+    // Update record with new k-mers
+    record
+        .tags_mut()
+        .push_string(tag, kmers_found.join(",").as_bytes());
+
     Ok(())
 }
 
+/// Check if the tag is a valid tag name of exactly two characters.
+fn validate_tag(tag: &str) -> Result<[u8; 2]> {
+    if tag.len() != 2 {
+        anyhow::bail!("Tag must be exactly two characters long.");
+    }
+    tag.as_bytes()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid tag format."))
+}
+
+/// Returns the k-mers found in the other segments (mates) of a template.
+fn mate_kmers(segments: &[(u16, Vec<String>)], own_segment: u16) -> Vec<String> {
+    segments
+        .iter()
+        .filter(|(segment, _)| *segment != own_segment)
+        .flat_map(|(_, kmers)| kmers.iter().cloned())
+        .collect()
+}
+
+/// Returns true if the header declares the records to be grouped by read
+/// name (`SO:queryname` or `GO:query` in the @HD line).
+fn is_name_collated(header: &bam::Header) -> bool {
+    header.lines().any(|line| match line {
+        HeaderLine::Entry(entry) if entry.entry_type() == EntryType::HeaderLine => {
+            entry.get(b"SO") == Some("queryname") || entry.get(b"GO") == Some("query")
+        }
+        _ => false,
+    })
+}
+
+/// Open a BAM or SAM file for reading and return the reader with the header.
+fn open_record_reader(
+    path: &Path,
+    extension: &str,
+    threads: u16,
+) -> Result<(Box<dyn RecordReader>, bam::Header)> {
+    match extension {
+        "bam" => {
+            // Open BAM file for reading with x additional threads for decompression
+            let reader = bam::BamReader::from_path(path, threads - 1)
+                .with_context(|| format!("Error reading BAM file: {path:?}"))?;
+            let header = reader.header().clone();
+            Ok((Box::new(reader), header))
+        }
+        "sam" => {
+            let reader = bam::SamReader::from_path(path)
+                .with_context(|| format!("Error reading SAM file: {path:?}"))?;
+            let header = reader.header().clone();
+            Ok((Box::new(reader), header))
+        }
+        _ => anyhow::bail!("Input file must be a BAM or SAM file."),
+    }
+}
+
+fn infer_record_writer(
+    threads: u16,
+    out_file: &Option<PathBuf>,
+    extension: &str,
+    header: bam::Header,
+) -> Result<Box<dyn RecordWriter>> {
+    match extension {
+        "bam" => {
+            let out_file = out_file
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Output file not provided for BAM writing."))?;
+            let path = out_file.with_extension(extension);
+            Ok(Box::new(
+                bam::bam_writer::BamWriterBuilder::new()
+                    .additional_threads(threads - 1)
+                    .from_path(&path, header)
+                    .with_context(|| format!("Error writing BAM file: {}", path.display()))?,
+            ))
+        }
+        "sam" => {
+            let out_file = out_file
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Output file not provided for SAM writing."))?;
+            let path = out_file.with_extension(extension);
+            Ok(Box::new(
+                bam::sam::SamWriterBuilder::new()
+                    .from_path(&path, header)
+                    .with_context(|| format!("Error writing SAM file: {}", path.display()))?,
+            ))
+        }
+        "STDOUT" => Ok(Box::new(
+            bam::sam::SamWriterBuilder::new()
+                .from_stream(io::stdout(), header)
+                .with_context(|| "Error writing SAM file to stdout.")?,
+        )),
+        _ => anyhow::bail!("Invalid output file type. Must be BAM or SAM file."),
+    }
+}
+
 //
 // ---------------------------------- Tests ----------------------------------
 //
@@ -698,6 +1079,51 @@ mod tests {
     use std::fs;
     use std::path::Path;
 
+    /// Options with the defaults of the command line, to be completed by
+    /// each test.
+    fn test_args() -> CmdTag {
+        CmdTag {
+            in_file: PathBuf::new(),
+            out_file: None,
+            kmer_seq: None,
+            kmer_file: None,
+            reverse_complement: false,
+            canonical: false,
+            tag: "km".to_string(),
+            out_log: None,
+            log_level: LogLevel::Hit,
+            json_log: None,
+            json_format: JsonFormat::Pretty,
+            threads: 1,
+            min_base_qual: None,
+            min_mean_qual: None,
+            min_length: None,
+            max_length: None,
+            max_n_fraction: None,
+            exclude_flags: 0,
+            min_mapq: None,
+            skip_unmapped: false,
+            suppress_output: false,
+            filter_matching: false,
+            invert_match: false,
+            unmatched_out: None,
+            keep_mates: false,
+            coverage: None,
+            coverage_bin: 1,
+            coverage_unit: CoverageUnit::Reads,
+            hits_parquet: None,
+            mate_tag: "mk".to_string(),
+            case_insensitive: false,
+            lowercase: false,
+            uppercase: false,
+            q_size: None,
+            aho_corasick: false,
+            config: None,
+            preset: None,
+            config_values: ConfigValues::default(),
+        }
+    }
+
     // Simple test which does not validate the output!
     #[test]
     fn test_tag_records_no_panics() {
@@ -716,20 +1142,12 @@ mod tests {
             kmer_seq: Some(kmer_seq),
             kmer_file,
             reverse_complement,
-            canonical: false,
             tag,
             filter_matching: keep_matching,
             out_log,
-            json_log: None,
             threads,
             out_file,
-            suppress_output: false,
-            invert_match: false,
-            q_size: None,
-            aho_corasick: false,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         tag_records(args).unwrap();
@@ -755,20 +1173,12 @@ mod tests {
             kmer_seq: Some(kmer_seq),
             kmer_file,
             reverse_complement,
-            canonical: false,
             tag,
             filter_matching: keep_matching,
             out_log,
-            json_log: None,
             threads,
             out_file,
-            suppress_output: false,
-            invert_match: false,
-            q_size: None,
-            aho_corasick: false,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         tag_records(args).unwrap();
@@ -794,20 +1204,12 @@ mod tests {
             kmer_seq: Some(kmer_seq),
             kmer_file,
             reverse_complement,
-            canonical: false,
             tag,
             filter_matching: keep_matching,
             out_log,
-            json_log: None,
             threads,
             out_file,
-            suppress_output: false,
-            invert_match: false,
-            q_size: None,
-            aho_corasick: false,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         tag_records(args).unwrap();
@@ -1020,21 +1422,12 @@ mod tests {
             in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
             out_file: Some(out_sam.clone()),
             kmer_seq: Some(vec!["CTC".to_string()]),
-            kmer_file: None,
             reverse_complement: true,
-            canonical: false,
-            tag: "km".to_string(),
             filter_matching: true,
             out_log: Some(out_log.clone()),
             json_log: Some(out_json.clone()),
             threads: 2,
-            suppress_output: false,
-            invert_match: false,
-            q_size: None,
-            aho_corasick: false,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         tag_records(args)?;
@@ -1062,21 +1455,12 @@ mod tests {
             in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
             out_file: Some(out_sam.clone()),
             kmer_seq: Some(vec!["CTC".to_string()]),
-            kmer_file: None,
             reverse_complement: true,
-            canonical: false,
-            tag: "km".to_string(),
-            filter_matching: false,
             out_log: Some(out_log.clone()),
             json_log: Some(out_json.clone()),
             threads: 2,
-            suppress_output: false,
             invert_match: true,
-            q_size: None,
-            aho_corasick: false,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         tag_records(args)?;
@@ -1104,21 +1488,12 @@ mod tests {
             in_file: PathBuf::from("tests/fixtures/input/simple.bam"),
             out_file: Some(out_sam.clone()),
             kmer_seq: Some(vec!["CTC".to_string()]),
-            kmer_file: None,
             reverse_complement: true,
-            canonical: false,
-            tag: "km".to_string(),
-            filter_matching: false, // No -m flag
+ // No -m flag
             out_log: Some(out_log.clone()),
             json_log: Some(out_json.clone()),
             threads: 2,
-            suppress_output: false,
-            invert_match: false,
-            q_size: None,
-            aho_corasick: false,
-            case_insensitive: false,
-            lowercase: false,
-            uppercase: false,
+            ..test_args()
         };
 
         tag_records(args)?;
@@ -1131,5 +1506,287 @@ mod tests {
         Ok(())
     }
 
+    // Keep mates of matching records from a coordinate-sorted SAM file (two passes)
+    // Corresponds to: cargo run -- tag -i tests/fixtures/input/simple.sam -o tests/fixtures/tag/simple-mates.extracted.sam -s CTC -r -m --keep-mates
+    #[test]
+    fn test_tag_keep_mates_coordinate_sorted() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_sam = temp_dir.path().join("out.sam");
+        let out_json = temp_dir.path().join("out.json");
+
+        let args = CmdTag {
+            in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
+            out_file: Some(out_sam.clone()),
+            kmer_seq: Some(vec!["CTC".to_string()]),
+            reverse_complement: true,
+            filter_matching: true,
+            json_log: Some(out_json.clone()),
+            keep_mates: true,
+            ..test_args()
+        };
+
+        tag_records(args)?;
+
+        compare_sam_output(&out_sam, "tests/fixtures/tag/simple-mates.extracted.sam")?;
+        // Records are only counted once, in the first pass
+        compare_json_output(&out_json, "tests/fixtures/tag/simple.json")?;
+
+        Ok(())
+    }
+
+    // Keep mates of matching records from a name-collated SAM file (streaming)
+    // Corresponds to: cargo run -- tag -i tests/fixtures/input/simple-collated.sam -o tests/fixtures/tag/simple-collated-mates.extracted.sam -s CTC -r -m --keep-mates
+    #[test]
+    fn test_tag_keep_mates_name_collated() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_sam = temp_dir.path().join("out.sam");
+
+        let args = CmdTag {
+            in_file: PathBuf::from("tests/fixtures/input/simple-collated.sam"),
+            out_file: Some(out_sam.clone()),
+            kmer_seq: Some(vec!["CTC".to_string()]),
+            reverse_complement: true,
+            filter_matching: true,
+            keep_mates: true,
+            ..test_args()
+        };
+
+        tag_records(args)?;
+
+        compare_sam_output(&out_sam, "tests/fixtures/tag/simple-collated-mates.extracted.sam")?;
+
+        Ok(())
+    }
+
+    // Inverted matching drops both mates if one of them matches
+    #[test]
+    fn test_tag_keep_mates_inverted() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_sam = temp_dir.path().join("out.sam");
+
+        let args = CmdTag {
+            in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
+            out_file: Some(out_sam.clone()),
+            kmer_seq: Some(vec!["AGCTTAG".to_string()]),
+            invert_match: true,
+            keep_mates: true,
+            ..test_args()
+        };
+
+        tag_records(args)?;
+
+        let out = fs::read_to_string(&out_sam)?;
+        let records: Vec<&str> = out.lines().filter(|l| !l.starts_with('@')).collect();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].starts_with("ABC-1:1:101\t"));
+
+        Ok(())
+    }
+
+    // Records filtered out with -m are written to the unmatched output file
+    #[test]
+    fn test_tag_unmatched_out() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let out_sam = temp_dir.path().join("out.sam");
+        let unmatched_sam = temp_dir.path().join("rest.sam");
+
+        let args = CmdTag {
+            in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
+            out_file: Some(out_sam.clone()),
+            kmer_seq: Some(vec!["AGCTTAG".to_string()]),
+            filter_matching: true,
+            unmatched_out: Some(unmatched_sam.clone()),
+            ..test_args()
+        };
+
+        tag_records(args)?;
+
+        let records = |path: &Path| -> Vec<String> {
+            fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .filter(|l| !l.starts_with('@'))
+                .map(|l| l.split('\t').next().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(records(&out_sam), vec!["ABC-1:1:100"]);
+        assert_eq!(records(&unmatched_sam), vec!["ABC-1:1:100", "ABC-1:1:101"]);
+
+        Ok(())
+    }
+
+    // Hits are only counted if all their bases pass the quality threshold
+    #[test]
+    fn test_tag_min_base_qual() -> Result<()> {
+        for (min_base_qual, expected_records) in [(37, 1), (38, 0)] {
+            let temp_dir = tempfile::tempdir()?;
+            let out_sam = temp_dir.path().join("out.sam");
+
+            let args = CmdTag {
+                in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
+                out_file: Some(out_sam.clone()),
+                kmer_seq: Some(vec!["AGCTTAG".to_string()]),
+                filter_matching: true,
+                min_base_qual: Some(min_base_qual),
+                ..test_args()
+            };
+
+            tag_records(args)?;
+
+            let out = fs::read_to_string(&out_sam)?;
+            let records = out.lines().filter(|l| !l.starts_with('@')).count();
+            assert_eq!(records, expected_records, "--min-base-qual {min_base_qual}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tag_alignment_filters() -> Result<()> {
+        for (exclude_flags, min_mapq, expected_tagged) in [(0, None, 1), (0x40, None, 0), (0, Some(61), 0)] {
+            let temp_dir = tempfile::tempdir()?;
+            let out_sam = temp_dir.path().join("out.sam");
+
+            let args = CmdTag {
+                in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
+                out_file: Some(out_sam.clone()),
+                kmer_seq: Some(vec!["AGCTTAG".to_string()]),
+                exclude_flags,
+                min_mapq,
+                ..test_args()
+            };
+
+            tag_records(args)?;
+
+            // Skipped records are passed through untouched
+            let out = fs::read_to_string(&out_sam)?;
+            let records: Vec<&str> = out.lines().filter(|l| !l.starts_with('@')).collect();
+            assert_eq!(records.len(), 3);
+            let tagged = records.iter().filter(|l| l.contains("km:Z:AGCTTAG")).count();
+            assert_eq!(tagged, expected_tagged, "-F {exclude_flags} --min-mapq {min_mapq:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_sam_flags() {
+        assert_eq!(parse_sam_flags("0x904"), Ok(0x904));
+        assert_eq!(parse_sam_flags("2308"), Ok(2308));
+        assert!(parse_sam_flags("0xZZ").is_err());
+    }
+
+    #[test]
+    fn test_tag_coverage_bedgraph() -> Result<()> {
+        let cases = [
+            (CoverageUnit::Reads, 1, "1\t999\t1019\t1\n1\t1099\t1119\t1\n"),
+            (CoverageUnit::Reads, 100, "1\t900\t1000\t1\n1\t1000\t1100\t2\n1\t1100\t1200\t1\n"),
+            (
+                CoverageUnit::Hits,
+                1,
+                "1\t1013\t1019\t1\n1\t1101\t1105\t1\n1\t1105\t1107\t2\n1\t1107\t1109\t1\n\
+                 1\t1109\t1111\t2\n1\t1111\t1113\t1\n1\t1113\t1115\t2\n1\t1115\t1119\t1\n",
+            ),
+        ];
+        for (coverage_unit, coverage_bin, expected) in cases {
+            let temp_dir = tempfile::tempdir()?;
+            let coverage_path = temp_dir.path().join("coverage.bedgraph");
+
+            let args = CmdTag {
+                in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
+                kmer_seq: Some(vec!["CGATCG".to_string()]),
+                suppress_output: true,
+                coverage: Some(coverage_path.clone()),
+                coverage_bin,
+                coverage_unit,
+                ..test_args()
+            };
+
+            tag_records(args)?;
+
+            assert_eq!(
+                fs::read_to_string(&coverage_path)?,
+                expected,
+                "--coverage-unit {coverage_unit:?} --coverage-bin {coverage_bin}"
+            );
+        }
+
+        Ok(())
+    }
+
+    // Hits of mapped records are located on the reference
+    // Corresponds to: cargo run -- tag -i tests/fixtures/input/simple.sam -s CGATCG -S --hits-parquet hits.parquet
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_tag_hits_parquet() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let table_path = temp_dir.path().join("hits.parquet");
+        let args = CmdTag {
+            in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
+            kmer_seq: Some(vec!["CGATCG".to_string()]),
+            suppress_output: true,
+            hits_parquet: Some(table_path.clone()),
+            ..test_args()
+        };
+
+        tag_records(args)?;
+
+        let (rows, summary) = crate::hit_table::tests::read_hit_table(&table_path);
+        let positions: Vec<(u64, Option<String>, Option<u64>)> =
+            rows.into_iter().map(|(_, _, pos, _, reference, ref_pos)| (pos, reference, ref_pos)).collect();
+        let chr1 = || Some("1".to_string());
+        assert_eq!(
+            positions,
+            [
+                (14, chr1(), Some(1013)),
+                (2, chr1(), Some(1101)),
+                (6, chr1(), Some(1105)),
+                (10, chr1(), Some(1109)),
+                (14, chr1(), Some(1113)),
+            ]
+        );
+        assert_eq!(summary["summary_statistics"]["number_of_matches"], 5);
+        assert_eq!(summary["meta_information"]["subcommand"], "tag");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tag_logs_identical_for_both_algorithms() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let mut logs = Vec::new();
+        for aho_corasick in [true, false] {
+            let log_path = temp_dir.path().join(format!("{aho_corasick}.log"));
+            let json_path = temp_dir.path().join(format!("{aho_corasick}.json"));
+            let args = CmdTag {
+                in_file: PathBuf::from("tests/fixtures/input/simple.sam"),
+                kmer_seq: Some(vec!["CG".to_string(), "CGA".to_string(), "GAT".to_string(), "CTC".to_string()]),
+                reverse_complement: true,
+                out_log: Some(log_path.clone()),
+                json_log: Some(json_path.clone()),
+                suppress_output: true,
+                min_base_qual: Some(30),
+                q_size: if aho_corasick { None } else { Some(1) },
+                aho_corasick,
+                ..test_args()
+            };
+
+            tag_records(args)?;
+
+            // Compare without the header (timestamp and command line) and
+            // the meta information
+            let log: Vec<String> = fs::read_to_string(&log_path)?.lines().skip(4).map(String::from).collect();
+            let mut json: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path)?)?;
+            json["meta_information"] = serde_json::Value::Null;
+            logs.push((log, json));
+        }
+
+        assert!(logs[0].0.iter().any(|line| !line.starts_with('#')));
+        assert_eq!(logs[0].0, logs[1].0, "Text logs differ");
+        assert_eq!(logs[0].1, logs[1].1, "JSON logs differ");
+
+        Ok(())
+    }
+
     // TODO: Add tests for BAM output - not as easy because of BAM comparison.
 }
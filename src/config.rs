@@ -0,0 +1,502 @@
+//! # Configuration files with presets for repeatable runs.
+//!
+//! A TOML file given with `--config` sets options of the subcommands, using
+//! the long option names as keys (with `_` or `-`). The `[extract]` and `[tag]`
+//! sections apply to each subcommand, and named presets, selected with
+//! `--preset`, are applied on top of them. Options of a preset apply to both
+//! subcommands, unless they are in its `extract` or `tag` subsection:
+//!
+//! ```toml
+//! [extract]
+//! reverse_complement = true
+//!
+//! [tag]
+//! tag = "MK"
+//! threads = 8
+//!
+//! [presets.strict]
+//! min_base_qual = 20
+//! q_size = 4
+//!
+//! [presets.strict.tag]
+//! filter_matching = true
+//! ```
+//!
+//! Options of a preset for both subcommands that only one of them has are
+//! skipped for the other. The options are added to the command line before
+//! parsing, so options given on the command line take precedence over the
+//! file, which also drops the options conflicting with them (e.g. `q_size`
+//! when `--aho-corasick` is given). The logs list the options applied from
+//! the file and those overridden by the command line.
+
+use anyhow::{Context, Result};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, Command};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Sections of the configuration file for the subcommands.
+const SUBCOMMANDS: [&str; 2] = ["extract", "tag"];
+
+/// Arguments that cannot be set in the configuration file.
+const RESERVED_ARGS: [&str; 4] = ["config", "preset", "help", "version"];
+
+/// Options read from a configuration file for a subcommand, as recorded in
+/// the logs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigValues {
+    /// Options added to the command line, in the order they are applied.
+    pub applied: Vec<(String, toml::Value)>,
+    /// Options not applied because the command line sets them, or options
+    /// that cannot be combined with them.
+    pub overridden: Vec<(String, toml::Value)>,
+}
+
+/// Options read from a configuration file, with whether they come from a
+/// preset for all subcommands.
+type FileOptions = Vec<(String, toml::Value, bool)>;
+
+/// Adds the options of the configuration file given with `--config` (and
+/// `--preset`) to the command line `argv` of the program `cmd`, right after
+/// the subcommand. Options given on the command line are not added, nor
+/// those conflicting with them, and options of a preset for all subcommands
+/// are skipped for subcommands without them. Returns
+/// the new command line and the options applied from the file or overridden
+/// by the command line, or the command line unchanged if no configuration
+/// file is given.
+pub fn expand_config_args(cmd: &Command, mut argv: Vec<OsString>) -> Result<(Vec<OsString>, ConfigValues)> {
+    let mut cmd = cmd.clone();
+    cmd.build();
+    // Invalid command lines are reported by the actual parsing
+    let Ok(matches) = cmd.clone().ignore_errors(true).try_get_matches_from(&argv) else {
+        return Ok((argv, ConfigValues::default()));
+    };
+    let Some((subcommand, sub_matches)) = matches.subcommand() else {
+        return Ok((argv, ConfigValues::default()));
+    };
+    let Some(path) = sub_matches.try_get_one::<PathBuf>("config").ok().flatten() else {
+        return Ok((argv, ConfigValues::default()));
+    };
+    let preset = sub_matches.try_get_one::<String>("preset").ok().flatten();
+    let sub_cmd = cmd
+        .find_subcommand(subcommand)
+        .ok_or_else(|| anyhow::anyhow!("Unknown subcommand '{subcommand}'."))?;
+
+    let cli_args: Vec<&Arg> = sub_cmd
+        .get_arguments()
+        .filter(|arg| sub_matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect();
+
+    let mut config_args = Vec::new();
+    let mut values = ConfigValues::default();
+    for (key, value, shared) in read_config(path, subcommand, preset.map(String::as_str))? {
+        let Some(arg) = find_option(sub_cmd, &key) else {
+            // Presets for all subcommands may have options of another one
+            if shared && cmd.get_subcommands().any(|other| find_option(other, &key).is_some()) {
+                continue;
+            }
+            anyhow::bail!(
+                "Unknown option '{key}' for '{subcommand}' in configuration file: {}",
+                path.display()
+            );
+        };
+        // Options given on the command line take precedence, also over the
+        // options they cannot be combined with
+        if cli_args.iter().any(|cli_arg| cli_arg.get_id() == arg.get_id() || args_conflict(sub_cmd, arg, cli_arg)) {
+            values.overridden.push((key, value));
+            continue;
+        }
+        config_args.extend(
+            option_args(arg, &value)
+                .with_context(|| format!("Invalid value of '{key}' in configuration file: {}", path.display()))?,
+        );
+        values.applied.push((key, value));
+    }
+
+    let pos = argv
+        .iter()
+        .skip(1)
+        .position(|a| a.to_str() == Some(subcommand))
+        .map_or(argv.len(), |i| i + 2);
+    argv.splice(pos..pos, config_args);
+    Ok((argv, values))
+}
+
+/// Parses the options of the subcommand `name` given as command-line
+/// arguments (without the program and subcommand names), applying the
+/// configuration file given with `--config` like the command line does.
+pub(crate) fn parse_subcommand_args<T, I, A>(name: &'static str, args: I) -> Result<(T, ConfigValues)>
+where
+    T: clap::Args + clap::FromArgMatches,
+    I: IntoIterator<Item = A>,
+    A: Into<OsString>,
+{
+    let cmd = Command::new(clap::crate_name!()).subcommand(T::augment_args(Command::new(name)));
+    let argv = [OsString::from(clap::crate_name!()), OsString::from(name)]
+        .into_iter()
+        .chain(args.into_iter().map(Into::into))
+        .collect();
+    let (argv, config_values) = expand_config_args(&cmd, argv)?;
+    let matches = cmd.try_get_matches_from(argv)?;
+    let sub_matches = matches
+        .subcommand_matches(name)
+        .ok_or_else(|| anyhow::anyhow!("Missing subcommand '{name}'."))?;
+    Ok((T::from_arg_matches(sub_matches)?, config_values))
+}
+
+/// Returns the argument of a subcommand that can be set in the configuration
+/// file with `key`.
+fn find_option<'a>(cmd: &'a Command, key: &str) -> Option<&'a Arg> {
+    cmd.get_arguments()
+        .find(|arg| arg.get_id() == key && arg.get_long().is_some())
+        .filter(|_| !RESERVED_ARGS.contains(&key))
+}
+
+/// Returns true if two arguments cannot be used together: if one conflicts
+/// with the other, or both are in a group allowing only one of its arguments.
+fn args_conflict(cmd: &Command, a: &Arg, b: &Arg) -> bool {
+    let conflicts_with = |x: &Arg, y: &Arg| cmd.get_arg_conflicts_with(x).iter().any(|c| c.get_id() == y.get_id());
+    conflicts_with(a, b)
+        || conflicts_with(b, a)
+        || cmd.get_groups().any(|group| {
+            !group.clone().is_multiple()
+                && group.get_args().any(|id| id == a.get_id())
+                && group.get_args().any(|id| id == b.get_id())
+        })
+}
+
+/// Reads the options for a subcommand from a configuration file: the
+/// subcommand section, then the options of the preset for all subcommands,
+/// then those of the preset for this subcommand. Later options replace
+/// earlier ones with the same name.
+fn read_config(path: &Path, subcommand: &str, preset: Option<&str>) -> Result<FileOptions> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Could not read configuration file: {}", path.display()))?;
+    let table: toml::Table = text
+        .parse()
+        .with_context(|| format!("Could not parse configuration file: {}", path.display()))?;
+
+    if let Some(key) = table
+        .keys()
+        .find(|k| !SUBCOMMANDS.contains(&k.as_str()) && k.as_str() != "presets")
+    {
+        anyhow::bail!(
+            "Unknown section '{key}' in configuration file {}; expected 'extract', 'tag' or 'presets'.",
+            path.display()
+        );
+    }
+
+    let mut values = FileOptions::new();
+    if let Some(section) = table.get(subcommand) {
+        add_options(&mut values, section, subcommand)?;
+    }
+    if let Some(name) = preset {
+        let presets = table.get("presets").and_then(toml::Value::as_table);
+        let Some(preset_table) = presets.and_then(|p| p.get(name)).and_then(toml::Value::as_table) else {
+            let available: Vec<&str> = presets.into_iter().flat_map(|p| p.keys()).map(String::as_str).collect();
+            anyhow::bail!(
+                "Preset '{name}' not found in configuration file {} (available: {}).",
+                path.display(),
+                if available.is_empty() { "none".to_string() } else { available.join(", ") }
+            );
+        };
+        for (key, value) in preset_table {
+            if !SUBCOMMANDS.contains(&key.as_str()) {
+                set_option(&mut values, key, value.clone(), true);
+            }
+        }
+        if let Some(section) = preset_table.get(subcommand) {
+            add_options(&mut values, section, &format!("presets.{name}.{subcommand}"))?;
+        }
+    }
+    Ok(values)
+}
+
+/// Adds the options of a section, which has to be a table.
+fn add_options(values: &mut FileOptions, section: &toml::Value, name: &str) -> Result<()> {
+    let table = section
+        .as_table()
+        .ok_or_else(|| anyhow::anyhow!("Section '{name}' of the configuration file must be a table."))?;
+    for (key, value) in table {
+        set_option(values, key, value.clone(), false);
+    }
+    Ok(())
+}
+
+/// Sets an option, replacing an earlier value. Keys are normalized to the
+/// argument ids, with underscores.
+fn set_option(values: &mut FileOptions, key: &str, value: toml::Value, shared: bool) {
+    let key = key.replace('-', "_");
+    values.retain(|(k, _, _)| *k != key);
+    values.push((key, value, shared));
+}
+
+/// Returns the command-line arguments setting an option to a value.
+fn option_args(arg: &Arg, value: &toml::Value) -> Result<Vec<OsString>> {
+    let long = format!("--{}", arg.get_long().unwrap_or_default());
+    if !arg.get_action().takes_values() {
+        return match value {
+            toml::Value::Boolean(true) => Ok(vec![long.into()]),
+            toml::Value::Boolean(false) => Ok(Vec::new()),
+            _ => anyhow::bail!("Expected true or false."),
+        };
+    }
+    match value {
+        toml::Value::Array(items) => {
+            if !matches!(arg.get_action(), ArgAction::Append) {
+                anyhow::bail!("Expected a single value, not a list.");
+            }
+            items
+                .iter()
+                .map(|item| Ok(format!("{long}={}", scalar_value(item)?).into()))
+                .collect()
+        }
+        _ => Ok(vec![format!("{long}={}", scalar_value(value)?).into()]),
+    }
+}
+
+fn scalar_value(value: &toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        _ => anyhow::bail!("Expected a string, number or boolean."),
+    }
+}
+
+/// Lists options as `key = value` for the log header.
+fn options_text(options: &[(String, toml::Value)]) -> String {
+    if options.is_empty() {
+        return "none".to_string();
+    }
+    let options: Vec<String> = options.iter().map(|(key, value)| format!("{key} = {value}")).collect();
+    options.join(", ")
+}
+
+/// Lists options as a JSON object for the JSON log.
+fn options_json(options: &[(String, toml::Value)]) -> serde_json::Value {
+    options
+        .iter()
+        .map(|(key, value)| (key.clone(), serde_json::to_value(value).unwrap_or_default()))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+/// Returns the log header line of the options applied from a configuration
+/// file and of those overridden by the command line, or an empty string if
+/// none is used.
+pub fn config_header(file: Option<&Path>, preset: Option<&str>, values: &ConfigValues) -> String {
+    let Some(file) = file else {
+        return String::new();
+    };
+    format!(
+        "#Configuration file: {}{}; applied options: {}; overridden by the command line: {}\n",
+        file.display(),
+        preset.map(|p| format!(" (preset '{p}')")).unwrap_or_default(),
+        options_text(&values.applied),
+        options_text(&values.overridden),
+    )
+}
+
+/// Returns the configuration file, preset and applied and overridden options
+/// for the JSON log, or null if no configuration file is used.
+pub fn config_json(file: Option<&Path>, preset: Option<&str>, values: &ConfigValues) -> serde_json::Value {
+    let Some(file) = file else {
+        return serde_json::Value::Null;
+    };
+    serde_json::json!({
+        "file": file.to_string_lossy(),
+        "preset": preset,
+        "options": options_json(&values.applied),
+        "overridden_options": options_json(&values.overridden),
+    })
+}
+
+//
+// ---------------------------------- Tests ----------------------------------
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Args, Parser, Subcommand};
+    use std::io::Write;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(subcommand)]
+        cmd: TestCommands,
+    }
+
+    #[derive(Subcommand)]
+    enum TestCommands {
+        Extract(TestExtractArgs),
+        Tag(TestArgs),
+    }
+
+    #[derive(Args)]
+    #[clap(group(clap::ArgGroup::new("algorithm").multiple(false).args(["q_size", "aho_corasick"])))]
+    struct TestArgs {
+        #[clap(short = 'r', long, action(ArgAction::SetTrue))]
+        reverse_complement: bool,
+        #[clap(short = 'q', long)]
+        q_size: Option<usize>,
+        #[clap(short = 'a', long, action(ArgAction::SetTrue))]
+        aho_corasick: bool,
+        #[clap(short = 's', long, num_args = 1..)]
+        kmer_seq: Option<Vec<String>>,
+        #[clap(short = 'f', long, conflicts_with("kmer_seq"))]
+        kmer_file: Option<PathBuf>,
+        #[clap(long)]
+        config: Option<PathBuf>,
+        #[clap(long, requires("config"))]
+        preset: Option<String>,
+    }
+
+    #[derive(Args)]
+    struct TestExtractArgs {
+        #[command(flatten)]
+        common: TestArgs,
+        #[clap(long)]
+        flank: Option<usize>,
+    }
+
+    fn expand(config: &str, args: &[&str]) -> Result<(Vec<String>, ConfigValues)> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(config.as_bytes()).unwrap();
+        let path = file.path().to_str().unwrap();
+        let argv = args.iter().map(|a| a.replace("CONFIG", path).into()).collect();
+        let (argv, values) = expand_config_args(&<TestCli as clap::CommandFactory>::command(), argv)?;
+        Ok((argv.into_iter().map(|a| a.into_string().unwrap()).collect(), values))
+    }
+
+    #[test]
+    fn test_expand_config_args() {
+        let config = r#"
+            [extract]
+            reverse-complement = true
+            q_size = 4
+            kmer_seq = ["ACG", "TTT"]
+
+            [tag]
+            reverse_complement = false
+
+            [presets.fast]
+            q_size = 2
+
+            [presets.fast.extract]
+            q_size = 3
+        "#;
+        let (argv, values) = expand(config, &["merkurio", "extract", "--config", "CONFIG", "-q", "5"]).unwrap();
+        assert_eq!(argv[..5], ["merkurio", "extract", "--kmer-seq=ACG", "--kmer-seq=TTT", "--reverse-complement"]);
+        assert_eq!(argv[5..], ["--config", argv[6].as_str(), "-q", "5"]);
+        assert_eq!(values.applied.len(), 2);
+        assert_eq!(values.overridden, [("q_size".to_string(), toml::Value::Integer(4))]);
+
+        let (argv, values) = expand(config, &["merkurio", "extract", "--config", "CONFIG", "--preset", "fast"]).unwrap();
+        assert!(argv.contains(&"--q-size=3".to_string()));
+        assert_eq!(values.applied.last().unwrap(), &("q_size".to_string(), toml::Value::Integer(3)));
+        assert!(values.overridden.is_empty());
+
+        let (argv, values) = expand(config, &["merkurio", "tag", "--config=CONFIG", "--preset", "fast"]).unwrap();
+        assert_eq!(argv[2], "--q-size=2");
+        assert_eq!(values.applied.len(), 2);
+
+        let (argv, values) = expand(config, &["merkurio", "tag", "-r"]).unwrap();
+        assert_eq!(argv, ["merkurio", "tag", "-r"]);
+        assert_eq!(values, ConfigValues::default());
+    }
+
+    #[test]
+    fn test_config_conflicting_options() {
+        let config = r#"
+            [extract]
+            aho_corasick = true
+            kmer_file = "kmers.txt"
+            reverse_complement = true
+        "#;
+        let args = ["merkurio", "extract", "--config", "CONFIG", "-q", "2", "-s", "ACG"];
+        let (argv, values) = expand(config, &args).unwrap();
+        assert_eq!(argv[2], "--reverse-complement");
+        assert!(!argv.iter().any(|a| a.starts_with("--aho-corasick") || a.starts_with("--kmer-file")));
+        assert_eq!(values.applied, [("reverse_complement".to_string(), toml::Value::Boolean(true))]);
+        assert_eq!(
+            values.overridden,
+            [
+                ("aho_corasick".to_string(), toml::Value::Boolean(true)),
+                ("kmer_file".to_string(), toml::Value::String("kmers.txt".to_string())),
+            ]
+        );
+        assert!(TestCli::try_parse_from(argv).is_ok());
+
+        // Without conflicting options on the command line, all are applied
+        let (argv, values) = expand(config, &["merkurio", "extract", "--config", "CONFIG"]).unwrap();
+        assert_eq!(argv[2..5], ["--aho-corasick", "--kmer-file=kmers.txt", "--reverse-complement"]);
+        assert_eq!(values.applied.len(), 3);
+        assert!(values.overridden.is_empty());
+    }
+
+    #[test]
+    fn test_config_shared_preset() {
+        let config = r#"
+            [presets.p]
+            flank = 10
+            q_size = 2
+        "#;
+        let (argv, values) = expand(config, &["merkurio", "tag", "--config", "CONFIG", "--preset", "p"]).unwrap();
+        assert_eq!(argv[2], "--q-size=2");
+        assert_eq!(values.applied, [("q_size".to_string(), toml::Value::Integer(2))]);
+        assert!(values.overridden.is_empty());
+
+        let (argv, values) = expand(config, &["merkurio", "extract", "--config", "CONFIG", "--preset", "p"]).unwrap();
+        assert_eq!(argv[2..4], ["--flank=10", "--q-size=2"]);
+        assert_eq!(values.applied.len(), 2);
+
+        // Options of no subcommand, or in a section of the subcommand, are errors
+        let args = ["merkurio", "tag", "--config", "CONFIG", "--preset", "p"];
+        assert!(expand("[presets.p]\nunknown = 1", &args).is_err());
+        assert!(expand("[presets.p.tag]\nflank = 10", &args).is_err());
+        assert!(expand("[tag]\nflank = 10\n[presets.p]", &args).is_err());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let args = ["merkurio", "extract", "--config", "CONFIG"];
+        assert!(expand("[extract]\nunknown = 1", &args).is_err());
+        assert!(expand("[extract]\nconfig = 'other.toml'", &args).is_err());
+        assert!(expand("[extract]\nreverse_complement = 'yes'", &args).is_err());
+        assert!(expand("[extract]\nq_size = [1, 2]", &args).is_err());
+        assert!(expand("[other]\nq_size = 1", &args).is_err());
+        assert!(expand("extract = 1", &args).is_err());
+        assert!(expand("[extract", &args).is_err());
+        let err = expand("[presets.a]", &["merkurio", "extract", "--config", "CONFIG", "--preset", "b"]).unwrap_err();
+        assert!(err.to_string().contains("(available: a)"));
+    }
+
+    #[test]
+    fn test_config_log_output() {
+        let values = ConfigValues {
+            applied: vec![
+                ("q_size".to_string(), toml::Value::Integer(4)),
+                ("tag".to_string(), toml::Value::String("MK".to_string())),
+            ],
+            overridden: vec![("threads".to_string(), toml::Value::Integer(8))],
+        };
+        let file = Path::new("merkurio.toml");
+        assert_eq!(
+            config_header(Some(file), Some("strict"), &values),
+            "#Configuration file: merkurio.toml (preset 'strict'); applied options: q_size = 4, tag = \"MK\"; \
+             overridden by the command line: threads = 8\n"
+        );
+        assert!(config_header(Some(file), None, &ConfigValues::default()).ends_with("applied options: none; overridden by the command line: none\n"));
+        assert_eq!(config_header(None, None, &values), "");
+        let json = config_json(Some(file), None, &values);
+        assert_eq!(json["options"]["q_size"], 4);
+        assert_eq!(json["options"]["tag"], "MK");
+        assert_eq!(json["overridden_options"]["threads"], 8);
+        assert!(json["preset"].is_null());
+        assert!(config_json(None, None, &values).is_null());
+    }
+}
@@ -42,6 +42,22 @@ pub fn add_suffix_to_file_prefix(path: &Path, suffix: &str) -> PathBuf {
     pathbuf
 }
 
+/// Returns the part of a read name that is shared by both reads of a pair:
+/// the name is cut at the first whitespace (Illumina comment field) and a
+/// trailing `/1` or `/2` mate suffix is removed.
+/// E.g. "read1/1 1:N:0:ACGT" -> "read1"
+pub fn read_name_base(id: &[u8]) -> &[u8] {
+    let end = id
+        .iter()
+        .position(|c| c.is_ascii_whitespace())
+        .unwrap_or(id.len());
+    let name = &id[..end];
+    match name {
+        [base @ .., b'/', b'1' | b'2'] => base,
+        _ => name,
+    }
+}
+
 /// Get the file format from a file path, ignoring the compression extension if
 /// there is one. Returns an error if the file is a directory or has no
 /// extension.
@@ -67,6 +83,15 @@ pub fn identify_uncompressed_type(path: &Path) -> Result<String> {
     }
 }
 
+/// Origin of a pattern in the pattern list: a k-mer as given (after case
+/// conversion) and/or the reverse complement of a given k-mer. Canonical
+/// forms that differ from their k-mer count as reverse complements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PatternOrigin {
+    pub forward: bool,
+    pub reverse_complement: bool,
+}
+
 /// Read k-mers from file to a list (FASTA or one k-mer per line),
 /// convert sequence to lowercase or uppercase if flag is set,
 /// with or without reverse complements, or compute and use only canonical k-mers.
@@ -81,6 +106,20 @@ pub fn parse_pattern_list(
     lowercase: bool,
     uppercase: bool,
 ) -> Result<Vec<String>> {
+    parse_pattern_list_with_origins(kmer_file, kmer_seq, reverse_complement, canonical, lowercase, uppercase)
+        .map(|(pattern_list, _)| pattern_list)
+}
+
+/// Like `parse_pattern_list`, and also returns the origin of each pattern,
+/// recorded while the reverse complements or canonical forms are added.
+pub fn parse_pattern_list_with_origins(
+    kmer_file: &Option<PathBuf>,
+    kmer_seq: Option<Vec<String>>,
+    reverse_complement: bool,
+    canonical: bool,
+    lowercase: bool,
+    uppercase: bool,
+) -> Result<(Vec<String>, Vec<PatternOrigin>)> {
     // Prioritize reading from path over provided sequence
     let mut pattern_list = match kmer_file {
         Some(path) => read_kmers_from_file(path)
@@ -94,42 +133,58 @@ pub fn parse_pattern_list(
     } else if uppercase {
         pattern_list = pattern_list.iter().map(|s| s.to_uppercase()).collect();
     }
+    let forward = PatternOrigin { forward: true, reverse_complement: false };
+    let rev_compl = PatternOrigin { forward: false, reverse_complement: true };
+    let mut patterns: Vec<(String, PatternOrigin)> =
+        pattern_list.into_iter().map(|pattern| (pattern, forward)).collect();
 
     // Add reverse complements of k-mers to the list if flag is set
     if reverse_complement {
-        let rev_compl_list: Vec<String> = pattern_list
+        let rev_compl_list: Vec<(String, PatternOrigin)> = patterns
             .iter()
-            .map(|pattern| {
-                str::from_utf8(&pattern.as_bytes().reverse_complement())
+            .map(|(pattern, _)| {
+                let pattern = str::from_utf8(&pattern.as_bytes().reverse_complement())
                     .expect("Invalid UTF-8 in reverse complement k-mer.")
-                    .to_string()
+                    .to_string();
+                (pattern, rev_compl)
             })
             .collect();
-        pattern_list.extend(rev_compl_list);
+        patterns.extend(rev_compl_list);
     }
 
     // Use the canonical forms of k-mers
     if canonical {
-        pattern_list = pattern_list
-            .iter()
-            .map(|s| {
-                let seq = s.as_bytes();
-                let can = sequence::canonical(seq);
-                String::from_utf8(can.to_vec()).expect("Invalid UTF-8 in canonical k-mer")
-            })
-            .collect();
+        for (pattern, origin) in patterns.iter_mut() {
+            let can = sequence::canonical(pattern.as_bytes());
+            if can.as_ref() != pattern.as_bytes() {
+                *pattern = String::from_utf8(can.to_vec()).expect("Invalid UTF-8 in canonical k-mer");
+                *origin = rev_compl;
+            }
+        }
     }
 
-    // Sort pattern list and remove duplicates and empty patterns
-    pattern_list.retain(|x| !x.is_empty());
-    pattern_list.sort_unstable();
-    pattern_list.dedup();
+    // Sort pattern list and remove duplicates and empty patterns, keeping
+    // all origins of duplicates
+    patterns.retain(|(x, _)| !x.is_empty());
+    patterns.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    let mut pattern_list: Vec<String> = Vec::with_capacity(patterns.len());
+    let mut origins: Vec<PatternOrigin> = Vec::with_capacity(patterns.len());
+    for (pattern, origin) in patterns {
+        if pattern_list.last() == Some(&pattern) {
+            let last = origins.last_mut().unwrap();
+            last.forward |= origin.forward;
+            last.reverse_complement |= origin.reverse_complement;
+        } else {
+            pattern_list.push(pattern);
+            origins.push(origin);
+        }
+    }
 
     if pattern_list.is_empty() {
         anyhow::bail!("No k-mers found in file or provided sequence.");
     }
 
-    Ok(pattern_list)
+    Ok((pattern_list, origins))
 }
 
 /// Read k-mers from file and return them as a vector of strings.
@@ -162,6 +217,187 @@ pub fn read_kmers_from_file(path: &Path) -> Result<Vec<String>> {
     Ok(kmer_list)
 }
 
+/// Read k-mers together with their group from a file. In FASTA files, the
+/// group of a k-mer is the name of the preceding header (up to the first
+/// whitespace); k-mers without a header form their own group.
+/// Skips empty lines and comment lines like `read_kmers_from_file`.
+pub fn read_kmer_groups_from_file(path: &Path) -> Result<Vec<(String, String)>> {
+    error_if_directory(path, "K-mer file path")?;
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Error reading file: {}", path.display()))?;
+
+    let mut group: Option<String> = None;
+    let mut kmer_groups = Vec::new();
+    for line in content.lines() {
+        if let Some(header) = line.strip_prefix('>') {
+            group = header.split_whitespace().next().map(String::from);
+            continue;
+        }
+        let kmer = line.trim();
+        if kmer.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let name = group.clone().unwrap_or_else(|| kmer.to_string());
+        kmer_groups.push((name, kmer.to_string()));
+    }
+
+    if kmer_groups.is_empty() {
+        anyhow::bail!("No k-mers found in the file.");
+    }
+
+    Ok(kmer_groups)
+}
+
+/// Quality thresholds that the bases of a hit have to pass for the hit to
+/// count. Qualities are Phred scores, stored with an offset of 33 in FASTQ
+/// files and without offset in BAM/SAM records.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QualityFilter {
+    pub min_base_qual: Option<u8>,
+    pub min_mean_qual: Option<f64>,
+}
+
+impl QualityFilter {
+    pub fn is_active(&self) -> bool {
+        self.min_base_qual.is_some() || self.min_mean_qual.is_some()
+    }
+
+    /// Returns true if the bases of the hit at `start` with length `len` pass
+    /// the thresholds. Hits in records without qualities always pass.
+    pub fn passes(&self, qual: Option<&[u8]>, offset: u8, start: usize, len: usize) -> bool {
+        let Some(hit_qual) = qual.and_then(|q| q.get(start..start + len)) else {
+            return true;
+        };
+        if hit_qual.is_empty() {
+            return true;
+        }
+        if let Some(min) = self.min_base_qual
+            && hit_qual.iter().any(|&q| q.saturating_sub(offset) < min)
+        {
+            return false;
+        }
+        if let Some(min) = self.min_mean_qual {
+            let sum: u64 = hit_qual.iter().map(|&q| q.saturating_sub(offset) as u64).sum();
+            if (sum as f64 / hit_qual.len() as f64) < min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Read-level filters applied before matching. Records that do not pass them
+/// are not searched and count as records without a hit.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RecordFilter {
+    pub min_length: Option<usize>,
+    pub max_length: Option<usize>,
+    pub max_n_fraction: Option<f64>,
+}
+
+impl RecordFilter {
+    pub fn is_active(&self) -> bool {
+        self.min_length.is_some() || self.max_length.is_some() || self.max_n_fraction.is_some()
+    }
+
+    /// Returns true if the sequence passes the length and N content filters.
+    pub fn passes(&self, seq: &[u8]) -> bool {
+        if self.min_length.is_some_and(|min| seq.len() < min)
+            || self.max_length.is_some_and(|max| seq.len() > max)
+        {
+            return false;
+        }
+        if let Some(max) = self.max_n_fraction
+            && !seq.is_empty()
+        {
+            let nb_n = seq.iter().filter(|&&b| b == b'N' || b == b'n').count();
+            if nb_n as f64 / seq.len() as f64 > max {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// One sample of a batch run: a name and one or two read files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleEntry {
+    pub name: String,
+    pub in_1: PathBuf,
+    pub in_2: Option<PathBuf>,
+}
+
+/// Returns the file name of a path up to the first dot, used as sample name.
+/// E.g. "reads/sample.fastq.gz" -> "sample"
+pub fn sample_name_from_path(path: &Path) -> String {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Checks that a sample name can be used as a file name in the output
+/// directory, i.e. that it is not empty, "." or "..", and contains no path
+/// separators.
+pub fn validate_sample_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        anyhow::bail!(
+            "Invalid sample name '{name}'; sample names are used as file names and cannot contain path separators or be '.' or '..'."
+        );
+    }
+    Ok(())
+}
+
+/// Read a tab-separated sample sheet with the columns sample, R1 and an
+/// optional R2. Skips empty lines, lines starting with a '#' character and a
+/// header line (the first other line) whose first column is "sample".
+/// Relative read file paths are taken as they are, i.e. relative to the
+/// working directory.
+/// Returns error if a line is malformed, a sample name is invalid or used
+/// twice, or the sheet contains no samples.
+pub fn read_sample_sheet(path: &Path) -> Result<Vec<SampleEntry>> {
+    error_if_directory(path, "Sample sheet path")?;
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Error reading sample sheet: {}", path.display()))?;
+
+    let mut samples: Vec<SampleEntry> = Vec::new();
+    let mut first_line = true;
+    for (line_nb, line) in content.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+        if std::mem::take(&mut first_line) && fields[0].eq_ignore_ascii_case("sample") {
+            continue;
+        }
+        if fields.len() < 2 || fields.len() > 3 || fields[0].is_empty() || fields[1].is_empty() {
+            anyhow::bail!(
+                "Malformed line {} in sample sheet '{}'; expected the tab-separated columns sample, R1 and optionally R2.",
+                line_nb + 1,
+                path.display()
+            );
+        }
+        validate_sample_name(fields[0])
+            .with_context(|| format!("Line {} of sample sheet '{}'", line_nb + 1, path.display()))?;
+        if samples.iter().any(|s| s.name == fields[0]) {
+            anyhow::bail!("Sample '{}' is listed more than once in the sample sheet.", fields[0]);
+        }
+        samples.push(SampleEntry {
+            name: fields[0].to_string(),
+            in_1: PathBuf::from(fields[1]),
+            in_2: fields.get(2).filter(|f| !f.is_empty()).map(PathBuf::from),
+        });
+    }
+
+    if samples.is_empty() {
+        anyhow::bail!("No samples found in the sample sheet.");
+    }
+
+    Ok(samples)
+}
+
 /// Checks for conflicts when writing logs and regular output.
 ///
 /// - Returns an error if both `-l` and `-j` are provided without an argument
@@ -215,6 +451,7 @@ pub fn recommend_aho_corasick(pattern_list: &[String]) -> Result<bool> {
 //
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
     use std::path::PathBuf;
@@ -243,6 +480,120 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_read_name_base_suffix() {
+        assert_eq!(read_name_base(b"read1/1"), b"read1");
+        assert_eq!(read_name_base(b"read1/2"), b"read1");
+        assert_eq!(read_name_base(b"read1/3"), b"read1/3");
+    }
+
+    #[test]
+    fn test_read_name_base_comment() {
+        assert_eq!(
+            read_name_base(b"M0:1:FC:1:1101:1000:2000 1:N:0:ACGT"),
+            b"M0:1:FC:1:1101:1000:2000"
+        );
+        assert_eq!(read_name_base(b"read1/2\tcomment"), b"read1");
+        assert_eq!(read_name_base(b""), b"");
+    }
+
+    #[test]
+    fn test_read_kmer_groups_from_file() {
+        let kmer_groups =
+            read_kmer_groups_from_file(Path::new("tests/fixtures/input/kmer-groups.fasta")).unwrap();
+        assert_eq!(
+            kmer_groups,
+            vec![
+                ("groupA".to_string(), "ACG".to_string()),
+                ("groupA".to_string(), "CTT".to_string()),
+                ("groupB".to_string(), "TTT".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quality_filter() {
+        let qual = b"IIII####II";
+        let min_base = QualityFilter {
+            min_base_qual: Some(20),
+            min_mean_qual: None,
+        };
+        assert!(min_base.passes(Some(qual), 33, 0, 4));
+        assert!(!min_base.passes(Some(qual), 33, 2, 4));
+        assert!(min_base.passes(None, 33, 2, 4));
+        let min_mean = QualityFilter {
+            min_base_qual: None,
+            min_mean_qual: Some(20.0),
+        };
+        // Mean of 40, 40, 2, 2 is 21
+        assert!(min_mean.passes(Some(qual), 33, 2, 4));
+        assert!(!min_mean.passes(Some(qual), 33, 3, 4));
+        assert!(!QualityFilter::default().is_active());
+    }
+
+    #[test]
+    fn test_record_filter() {
+        let filter = RecordFilter {
+            min_length: Some(4),
+            max_length: Some(8),
+            max_n_fraction: Some(0.5),
+        };
+        assert!(filter.passes(b"ACGTNN"));
+        assert!(!filter.passes(b"ACG"));
+        assert!(!filter.passes(b"ACGTACGTA"));
+        assert!(!filter.passes(b"ACNNN"));
+        assert!(RecordFilter::default().passes(b""));
+    }
+
+    #[test]
+    fn test_sample_name_from_path() {
+        assert_eq!(sample_name_from_path(Path::new("reads/sample_1.fastq.gz")), "sample_1");
+        assert_eq!(sample_name_from_path(Path::new("sample")), "sample");
+    }
+
+    #[test]
+    fn test_read_sample_sheet() {
+        let samples = read_sample_sheet(Path::new("tests/fixtures/input/samples.tsv")).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].name, "simple");
+        assert_eq!(samples[0].in_2, None);
+        assert_eq!(samples[1].name, "paired");
+        assert_eq!(
+            samples[1].in_2,
+            Some(PathBuf::from("tests/fixtures/input/paired-2.fastq"))
+        );
+    }
+
+    #[test]
+    fn test_read_sample_sheet_duplicate_sample() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("samples.tsv");
+        fs::write(&path, "a\tr1.fastq\na\tr2.fastq\n").unwrap();
+        assert!(read_sample_sheet(&path).is_err());
+    }
+
+    #[test]
+    fn test_read_sample_sheet_header_after_comments() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("samples.tsv");
+        fs::write(&path, "# samples\n\nsample\tR1\na\tr1.fastq\n").unwrap();
+        let samples = read_sample_sheet(&path).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].name, "a");
+    }
+
+    #[test]
+    fn test_read_sample_sheet_invalid_sample_name() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("samples.tsv");
+        for name in ["../a", "a/b", "..", "a\\b"] {
+            fs::write(&path, format!("{name}\tr1.fastq\n")).unwrap();
+            assert!(read_sample_sheet(&path).is_err(), "{name}");
+        }
+        fs::write(&path, "a..b\tr1.fastq\n").unwrap();
+        assert!(read_sample_sheet(&path).is_ok());
+    }
+
     #[test]
     fn test_get_file_format_simple() {
         let path = Path::new("tests/data/sample.fasta");
@@ -396,6 +747,26 @@ mod tests {
         assert!(pattern_list.contains(&"AATAACCATCAAGAACAGCAACTTCTTCGGAG".to_string()));
     }
 
+    #[test]
+    fn test_parse_pattern_list_origins() {
+        let kmers = Some(vec!["ACG".to_string(), "AAT".to_string(), "GGT".to_string()]);
+        let (pattern_list, origins) =
+            parse_pattern_list_with_origins(&None, kmers.clone(), true, false, false, false).unwrap();
+        let forward = PatternOrigin { forward: true, reverse_complement: false };
+        let rev_compl = PatternOrigin { forward: false, reverse_complement: true };
+        assert_eq!(pattern_list, ["AAT", "ACC", "ACG", "ATT", "CGT", "GGT"]);
+        assert_eq!(origins, [forward, rev_compl, forward, rev_compl, rev_compl, forward]);
+
+        // A k-mer that is also the reverse complement of another one
+        let kmers = Some(vec!["ACG".to_string(), "CGT".to_string(), "AT".to_string()]);
+        let (_, origins) = parse_pattern_list_with_origins(&None, kmers.clone(), true, false, false, false).unwrap();
+        assert!(origins.iter().all(|o| o.forward && o.reverse_complement));
+
+        let (pattern_list, origins) = parse_pattern_list_with_origins(&None, kmers, false, true, false, false).unwrap();
+        assert_eq!(pattern_list, ["ACG", "AT"]);
+        assert_eq!(origins, [PatternOrigin { forward: true, reverse_complement: true }, forward]);
+    }
+
     #[test]
     fn test_parse_pattern_list_aa() {
         let pattern_list = parse_pattern_list(
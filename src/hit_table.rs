@@ -0,0 +1,284 @@
+//! # Columnar hit table
+//!
+//! Writes the hits to a Parquet file with typed columns, so they can be loaded
+//! into data frame libraries and databases (e.g. Polars, DuckDB) without
+//! parsing the logs:
+//!
+//! | Column               | Type   | Content                                                       |
+//! |----------------------|--------|---------------------------------------------------------------|
+//! | `file`               | string | Name of the file of the record                                |
+//! | `record_id`          | string | Record ID (invalid UTF-8 replaced)                            |
+//! | `record_id_hex`      | string | Record ID as hexadecimal bytes if it is not valid UTF-8       |
+//! | `pattern`            | string | Pattern (k-mer) found                                         |
+//! | `position`           | uint64 | Zero-based start position of the hit in the record           |
+//! | `strand`             | string | `+` for the k-mers, `-` for their reverse complements         |
+//! | `reference`          | string | Reference sequence of the alignment (SAM/BAM)                 |
+//! | `reference_position` | uint64 | Zero-based reference position of the first aligned hit base   |
+//!
+//! Columns that are not available are null. The summary of the JSON log is
+//! stored as JSON in the file metadata under the key `merkurio`. Writing the
+//! table needs the `parquet` feature, which is enabled by default.
+
+#[cfg(feature = "parquet")]
+use crate::logger::json_record_id;
+use std::io;
+use std::path::Path;
+
+/// Key of the summary in the metadata of the Parquet file.
+#[cfg(feature = "parquet")]
+pub const SUMMARY_KEY: &str = "merkurio";
+
+/// Location of a hit beyond its position in the record, if known.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HitLocation<'a> {
+    pub strand: Option<char>,
+    pub reference: Option<&'a str>,
+    pub reference_position: Option<u64>,
+}
+
+/// Number of hits per record batch (and at most per row group).
+#[cfg(feature = "parquet")]
+const BATCH_SIZE: usize = 64 * 1024;
+
+/// Writer of the hits to a Parquet file, in batches of rows.
+#[cfg(feature = "parquet")]
+pub struct HitTable {
+    writer: parquet::arrow::ArrowWriter<std::fs::File>,
+    schema: arrow_schema::SchemaRef,
+    file: arrow_array::builder::StringBuilder,
+    record_id: arrow_array::builder::StringBuilder,
+    record_id_hex: arrow_array::builder::StringBuilder,
+    pattern: arrow_array::builder::StringBuilder,
+    position: arrow_array::builder::UInt64Builder,
+    strand: arrow_array::builder::StringBuilder,
+    reference: arrow_array::builder::StringBuilder,
+    reference_position: arrow_array::builder::UInt64Builder,
+    nb_rows: usize,
+}
+
+#[cfg(feature = "parquet")]
+impl HitTable {
+    /// Creates the Parquet file at `path`.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        use arrow_schema::{DataType, Field, Schema};
+        use parquet::basic::Compression;
+        use parquet::file::properties::WriterProperties;
+        use std::sync::Arc;
+
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("file", DataType::Utf8, false),
+            Field::new("record_id", DataType::Utf8, false),
+            Field::new("record_id_hex", DataType::Utf8, true),
+            Field::new("pattern", DataType::Utf8, false),
+            Field::new("position", DataType::UInt64, false),
+            Field::new("strand", DataType::Utf8, true),
+            Field::new("reference", DataType::Utf8, true),
+            Field::new("reference_position", DataType::UInt64, true),
+        ]));
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(BATCH_SIZE)
+            .build();
+        let file = std::fs::File::create(path)?;
+        let writer = parquet::arrow::ArrowWriter::try_new(file, schema.clone(), Some(props))
+            .map_err(io::Error::other)?;
+
+        Ok(Self {
+            writer,
+            schema,
+            file: Default::default(),
+            record_id: Default::default(),
+            record_id_hex: Default::default(),
+            pattern: Default::default(),
+            position: Default::default(),
+            strand: Default::default(),
+            reference: Default::default(),
+            reference_position: Default::default(),
+            nb_rows: 0,
+        })
+    }
+
+    /// Adds a hit of `pattern` at `position` in a record.
+    pub fn log_hit(
+        &mut self,
+        file: &str,
+        record: &[u8],
+        pattern: &str,
+        position: usize,
+        location: HitLocation,
+    ) -> io::Result<()> {
+        let (id_str, id_hex) = json_record_id(record);
+        self.file.append_value(file);
+        self.record_id.append_value(id_str);
+        self.record_id_hex.append_option(id_hex);
+        self.pattern.append_value(pattern);
+        self.position.append_value(position as u64);
+        self.strand.append_option(location.strand.map(String::from));
+        self.reference.append_option(location.reference);
+        self.reference_position.append_option(location.reference_position);
+
+        self.nb_rows += 1;
+        if self.nb_rows >= BATCH_SIZE {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered rows as a record batch.
+    fn write_batch(&mut self) -> io::Result<()> {
+        use arrow_array::{ArrayRef, RecordBatch};
+        use std::sync::Arc;
+
+        if self.nb_rows == 0 {
+            return Ok(());
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.file.finish()),
+            Arc::new(self.record_id.finish()),
+            Arc::new(self.record_id_hex.finish()),
+            Arc::new(self.pattern.finish()),
+            Arc::new(self.position.finish()),
+            Arc::new(self.strand.finish()),
+            Arc::new(self.reference.finish()),
+            Arc::new(self.reference_position.finish()),
+        ];
+        let batch = RecordBatch::try_new(self.schema.clone(), columns).map_err(io::Error::other)?;
+        self.writer.write(&batch).map_err(io::Error::other)?;
+        self.nb_rows = 0;
+        Ok(())
+    }
+
+    /// Writes the remaining rows and the summary (see `logger::summary_json`)
+    /// as file metadata, and closes the file.
+    pub fn finalize(mut self, summary: &serde_json::Value) -> io::Result<()> {
+        self.write_batch()?;
+        self.writer.append_key_value_metadata(parquet::format::KeyValue::new(
+            SUMMARY_KEY.to_string(),
+            summary.to_string(),
+        ));
+        self.writer.close().map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+/// Placeholder when MerKurio is built without the `parquet` feature; the
+/// table cannot be created.
+#[cfg(not(feature = "parquet"))]
+pub struct HitTable(std::convert::Infallible);
+
+#[cfg(not(feature = "parquet"))]
+impl HitTable {
+    pub fn create(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "MerKurio was built without Parquet support (feature `parquet`).",
+        ))
+    }
+
+    pub fn log_hit(&mut self, _: &str, _: &[u8], _: &str, _: usize, _: HitLocation) -> io::Result<()> {
+        match self.0 {}
+    }
+
+    pub fn finalize(self, _summary: &serde_json::Value) -> io::Result<()> {
+        match self.0 {}
+    }
+}
+
+//
+// ---------------------------------- Tests ----------------------------------
+//
+
+#[cfg(all(test, feature = "parquet"))]
+pub(crate) mod tests {
+    use super::*;
+    use arrow_array::Array;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::UInt64Type;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    /// A row of a hit table: record ID, pattern, position, strand, reference
+    /// and reference position.
+    pub(crate) type HitRow = (String, String, u64, Option<String>, Option<String>, Option<u64>);
+
+    /// Reads the rows and the summary of a hit table.
+    pub(crate) fn read_hit_table(path: &Path) -> (Vec<HitRow>, serde_json::Value) {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(path).unwrap()).unwrap();
+        let summary = builder
+            .metadata()
+            .file_metadata()
+            .key_value_metadata()
+            .and_then(|kv| kv.iter().find(|kv| kv.key == SUMMARY_KEY))
+            .and_then(|kv| kv.value.as_deref())
+            .map(|value| serde_json::from_str(value).unwrap())
+            .unwrap_or_default();
+
+        let mut rows = Vec::new();
+        for batch in builder.build().unwrap() {
+            let batch = batch.unwrap();
+            let string = |name: &str| batch.column_by_name(name).unwrap().as_string::<i32>().clone();
+            let number = |name: &str| batch.column_by_name(name).unwrap().as_primitive::<UInt64Type>().clone();
+            let (record_id, pattern, strand, reference) =
+                (string("record_id"), string("pattern"), string("strand"), string("reference"));
+            let (position, reference_position) = (number("position"), number("reference_position"));
+            for i in 0..batch.num_rows() {
+                let opt_string = |col: &arrow_array::StringArray| col.is_valid(i).then(|| col.value(i).to_string());
+                rows.push((
+                    record_id.value(i).to_string(),
+                    pattern.value(i).to_string(),
+                    position.value(i),
+                    opt_string(&strand),
+                    opt_string(&reference),
+                    reference_position.is_valid(i).then(|| reference_position.value(i)),
+                ));
+            }
+        }
+        (rows, summary)
+    }
+
+    #[test]
+    fn test_hit_table_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("hits.parquet");
+        let mut table = HitTable::create(&path).unwrap();
+        table
+            .log_hit("reads.fq", b"read1", "ACG", 3, HitLocation { strand: Some('+'), ..Default::default() })
+            .unwrap();
+        table
+            .log_hit(
+                "reads.bam",
+                b"read\xff2",
+                "CGT",
+                0,
+                HitLocation { strand: None, reference: Some("chr1"), reference_position: Some(99) },
+            )
+            .unwrap();
+        // More hits than fit into one batch
+        for i in 0..BATCH_SIZE {
+            table.log_hit("reads.fq", b"read3", "ACG", i, HitLocation::default()).unwrap();
+        }
+        table.finalize(&serde_json::json!({"summary_statistics": {"number_of_matches": 2}})).unwrap();
+
+        let (rows, summary) = read_hit_table(&path);
+        assert_eq!(rows.len(), BATCH_SIZE + 2);
+        assert_eq!(rows[0], ("read1".to_string(), "ACG".to_string(), 3, Some("+".to_string()), None, None));
+        assert_eq!(
+            rows[1],
+            ("read\u{FFFD}2".to_string(), "CGT".to_string(), 0, None, Some("chr1".to_string()), Some(99))
+        );
+        assert_eq!(rows[BATCH_SIZE + 1].2, BATCH_SIZE as u64 - 1);
+        assert_eq!(summary["summary_statistics"]["number_of_matches"], 2);
+
+        // Non-UTF-8 record IDs are kept losslessly
+        let batch = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        let hex = batch.column_by_name("record_id_hex").unwrap().as_string::<i32>();
+        assert!(hex.is_null(0));
+        assert_eq!(hex.value(1), "72656164ff32");
+        assert_eq!(batch.column_by_name("position").unwrap().data_type(), &arrow_schema::DataType::UInt64);
+    }
+}
@@ -0,0 +1,65 @@
+//! # MerKurio
+//!
+//! Quick k-mer-based extraction of FASTA/FASTQ records, and filtering and
+//! tagging of SAM/BAM records. Besides the `merkurio` command-line tool, the
+//! search can be embedded in other Rust tools with this library:
+//!
+//! - [`PatternSet`]: reads and preprocesses the patterns (k-mers),
+//! - [`MultiPatternMatcher`]: searches for all patterns at once, with one of
+//!   the matchers of [`pattern_matching`] (BNDMq, BNDM or Aho-Corasick),
+//! - [`RecordFilter`] and [`QualityFilter`]: pre-filters for records and hits,
+//! - [`PatternStats`]: per-pattern statistics collected from [`HitEvent`]s,
+//! - [`RecordSearch`]: searches records with the filters and counts them and
+//!   their hits in [`SearchStats`], like the subcommands.
+//!
+//! The subcommands themselves are available as [`extract_records`] and
+//! [`tag_records`], with their options created by [`CmdExtract::from_args`]
+//! and [`CmdTag::from_args`] from the command-line arguments. Options of a
+//! configuration file (`--config`) are added to a command line with
+//! [`expand_config_args`].
+//!
+//! ```
+//! use merkurio::{PatternSet, PatternStats, QualityFilter, RecordFilter, RecordSearch, SearchStats};
+//!
+//! let patterns = PatternSet::builder().sequences(["ACG", "GTA"]).build().unwrap();
+//! let matcher = patterns.matcher().unwrap();
+//! let search = RecordSearch {
+//!     matcher: matcher.as_ref(),
+//!     quality_filter: QualityFilter { min_base_qual: Some(20), ..Default::default() },
+//!     record_filter: RecordFilter { min_length: Some(5), ..Default::default() },
+//!     // Qualities of FASTQ files are stored with an offset of 33
+//!     qual_offset: 33,
+//! };
+//! let mut stats = SearchStats::new(PatternStats::new(patterns.len()));
+//!
+//! let records: [(&[u8], &[u8]); 2] = [(b"ACGTACG", b"IIIIII!"), (b"ACG", b"III")];
+//! let mut hits = Vec::new();
+//! for (seq, qual) in records {
+//!     search.search(&mut stats, seq, Some(qual), None, &mut hits);
+//! }
+//! assert_eq!(hits, []);
+//! assert_eq!((stats.nb_records_tot, stats.nb_records_skipped, stats.nb_hits_rejected), (1, 1, 1));
+//! assert_eq!(stats.patterns.occurrences, [1, 1]);
+//! let summary = stats.summary_json(patterns.patterns(), true, true, false);
+//! assert_eq!(summary["number_of_matches"], 2);
+//! ```
+
+mod cmd_extract;
+mod cmd_tag;
+mod config;
+mod helpers;
+mod hit_table;
+mod logger;
+pub mod pattern_matching;
+pub mod pattern_preprocessing;
+pub mod pattern_set;
+pub mod stats;
+
+pub use cmd_extract::{CmdExtract, extract_records};
+pub use cmd_tag::{CmdTag, tag_records};
+pub use config::{ConfigValues, expand_config_args};
+pub use helpers::{QualityFilter, RecordFilter};
+pub use logger::json_record_id;
+pub use pattern_matching::MultiPatternMatcher;
+pub use pattern_set::{PatternSet, PatternSetBuilder};
+pub use stats::{HitEvent, PatternStats, RecordSearch, SearchStats, find_hits};
@@ -1,85 +1,305 @@
 //! Logger utilities
 
-use core::fmt::Write as FmtWrite;
+use clap::ValueEnum;
 use serde_json::json;
-use std::{
-    io::{self, Write},
-    str,
-};
+use std::io::{self, Write};
+
+/// Detail of the table of hits in the plain text log.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    /// One line per hit.
+    Hit,
+    /// One line per record with its number of hits, patterns and positions.
+    Record,
+    /// No table, only the header and the summary statistics.
+    Summary,
+}
+
+/// Hits of one record, collected for a line of the `LogLevel::Record` table.
+struct RecordHits {
+    file: String,
+    id: Vec<u8>,
+    record_nb: Option<usize>,
+    nb_hits: usize,
+    pattern_positions: Vec<(String, Vec<usize>)>,
+}
 
 /// A buffered logger that accumulates log records and writes them in batches.
+/// Logging runs in constant memory unless the records are retained with
+/// `keep_records`; without a writer, nothing is buffered. Record IDs are
+/// written as they are, even if they are not valid UTF-8.
 pub struct BufferedLogger {
-    buffer: String,
+    buffer: Vec<u8>,
     writer: Option<Box<dyn io::Write>>,
     buffer_size: usize,
-    records: Vec<String>,
+    records: Option<Vec<String>>,
+    level: LogLevel,
+    current_record: Option<RecordHits>,
 }
 
 impl BufferedLogger {
     /// Creates a new `BufferedLogger` with the given writer and buffer size.
+    #[cfg(test)]
     pub fn new(writer: Option<Box<dyn io::Write>>, buffer_size: usize) -> Self {
+        Self::with_level(writer, buffer_size, LogLevel::Hit)
+    }
+
+    /// Creates a new `BufferedLogger` listing hits at the given level.
+    pub fn with_level(writer: Option<Box<dyn io::Write>>, buffer_size: usize, level: LogLevel) -> Self {
         Self {
-            buffer: String::with_capacity(buffer_size),
+            buffer: Vec::with_capacity(buffer_size),
             writer,
             buffer_size,
-            records: Vec::new(),
+            records: None,
+            level,
+            current_record: None,
+        }
+    }
+
+    /// Retains a copy of all logged records, e.g. to inspect them in tests.
+    /// Memory grows with the number of records.
+    #[cfg(test)]
+    pub fn keep_records(mut self) -> Self {
+        self.records = Some(Vec::new());
+        self
+    }
+
+    /// Returns true if logged records are written or retained.
+    fn is_recording(&self) -> bool {
+        self.writer.is_some() || self.records.is_some()
+    }
+
+    /// Writes the column names of the table of hits.
+    pub fn write_column_header(&mut self) -> io::Result<()> {
+        match self.level {
+            LogLevel::Hit => self.write_header("#\n#File\tRecord\tPattern\tPosition (zero-based)\n"),
+            LogLevel::Record => self.write_header(
+                "#\n#File\tRecord\tNumber of hits\tPatterns\tPositions (zero-based, per pattern)\n",
+            ),
+            LogLevel::Summary => Ok(()),
         }
     }
 
     /// Logs a record to the buffer and writes to output if buffer is full.
-    pub fn log_record(&mut self, record: &str) {
-        self.records.push(record.to_string());
-        self.buffer.push_str(record);
+    #[cfg(test)]
+    pub fn log_record(&mut self, record: &str) -> io::Result<()> {
+        self.log_bytes(record.as_bytes())
+    }
+
+    /// Logs a line given as bytes, which may contain non-UTF-8 record IDs.
+    fn log_bytes(&mut self, line: &[u8]) -> io::Result<()> {
+        if let Some(records) = self.records.as_mut() {
+            records.push(String::from_utf8_lossy(line).into_owned());
+        }
+        if self.writer.is_none() {
+            return Ok(());
+        }
+        self.buffer.extend_from_slice(line);
 
         if self.buffer.len() >= self.buffer_size {
-            self.flush();
+            self.write_buffer()?;
         }
+        Ok(())
     }
 
     /// Logs the given fields directly to the buffer without constructing an
-    /// intermediate `String` for output.
-    pub fn log_fields(&mut self, prefix: &str, record: &[u8], pattern: &str, index: usize) {
-        let id_str = str::from_utf8(record).expect("Error during id parsing.");
-
-        // Store the record string for later retrieval
-        self.records
-            .push(format!("{prefix}\t{id_str}\t{pattern}\t{index}\n"));
-
-        self.buffer.push_str(prefix);
-        self.buffer.push('\t');
-        self.buffer.push_str(id_str);
-        self.buffer.push('\t');
-        self.buffer.push_str(pattern);
-        self.buffer.push('\t');
-        write!(self.buffer, "{index}").unwrap();
-        self.buffer.push('\n');
+    /// intermediate `String` for output. At record level, consecutive hits
+    /// with the same file and record ID are combined into one line.
+    #[cfg(test)]
+    pub fn log_fields(&mut self, prefix: &str, record: &[u8], pattern: &str, index: usize) -> io::Result<()> {
+        self.log_hit_fields(prefix, record, None, pattern, index)
+    }
+
+    /// Logs a hit like `log_fields`, with the running number of its record.
+    /// At record level, hits are combined by file and record number, so
+    /// records with the same ID (e.g. the mates of interleaved reads) are
+    /// listed on separate lines.
+    pub fn log_hit(
+        &mut self,
+        prefix: &str,
+        record: &[u8],
+        record_nb: usize,
+        pattern: &str,
+        index: usize,
+    ) -> io::Result<()> {
+        self.log_hit_fields(prefix, record, Some(record_nb), pattern, index)
+    }
+
+    fn log_hit_fields(
+        &mut self,
+        prefix: &str,
+        record: &[u8],
+        record_nb: Option<usize>,
+        pattern: &str,
+        index: usize,
+    ) -> io::Result<()> {
+        if !self.is_recording() {
+            return Ok(());
+        }
+        match self.level {
+            LogLevel::Hit => {}
+            LogLevel::Record => return self.add_record_hit(prefix, record, record_nb, pattern, index),
+            LogLevel::Summary => return Ok(()),
+        }
+
+        // Store the record string for later retrieval, if requested
+        if let Some(records) = self.records.as_mut() {
+            let id_str = String::from_utf8_lossy(record);
+            records.push(format!("{prefix}\t{id_str}\t{pattern}\t{index}\n"));
+        }
+        if self.writer.is_none() {
+            return Ok(());
+        }
+
+        self.buffer.extend_from_slice(prefix.as_bytes());
+        self.buffer.push(b'\t');
+        self.buffer.extend_from_slice(record);
+        self.buffer.push(b'\t');
+        self.buffer.extend_from_slice(pattern.as_bytes());
+        self.buffer.push(b'\t');
+        write!(self.buffer, "{index}")?;
+        self.buffer.push(b'\n');
 
         if self.buffer.len() >= self.buffer_size {
-            self.flush();
+            self.write_buffer()?;
+        }
+        Ok(())
+    }
+
+    /// Adds a hit to the current record, writing the line of the previous
+    /// record if the hit belongs to another one.
+    fn add_record_hit(
+        &mut self,
+        prefix: &str,
+        record: &[u8],
+        record_nb: Option<usize>,
+        pattern: &str,
+        index: usize,
+    ) -> io::Result<()> {
+        let same_record = self.current_record.as_ref().is_some_and(|current| {
+            current.file == prefix && current.id == record && current.record_nb == record_nb
+        });
+        if !same_record {
+            self.write_record_line()?;
+            self.current_record = Some(RecordHits {
+                file: prefix.to_string(),
+                id: record.to_vec(),
+                record_nb,
+                nb_hits: 0,
+                pattern_positions: Vec::new(),
+            });
+        }
+
+        let current = self.current_record.as_mut().unwrap();
+        current.nb_hits += 1;
+        match current.pattern_positions.iter_mut().find(|(p, _)| p == pattern) {
+            Some((_, positions)) => positions.push(index),
+            None => current.pattern_positions.push((pattern.to_string(), vec![index])),
         }
+        Ok(())
+    }
+
+    /// Writes the line of the current record at record level, e.g.
+    /// `reads.fq  read1  3  ACG,CGT  0,4;1` for two hits of ACG at positions
+    /// 0 and 4 and one hit of CGT at position 1.
+    fn write_record_line(&mut self) -> io::Result<()> {
+        let Some(current) = self.current_record.take() else {
+            return Ok(());
+        };
+        let patterns: Vec<&str> = current.pattern_positions.iter().map(|(p, _)| p.as_str()).collect();
+        let positions: Vec<String> = current
+            .pattern_positions
+            .iter()
+            .map(|(_, positions)| positions.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(","))
+            .collect();
+        let mut line = Vec::new();
+        line.extend_from_slice(current.file.as_bytes());
+        line.push(b'\t');
+        line.extend_from_slice(&current.id);
+        writeln!(line, "\t{}\t{}\t{}", current.nb_hits, patterns.join(","), positions.join(";"))?;
+        self.log_bytes(&line)
     }
 
     /// Writes a header directly to the output without buffering.
-    pub fn write_header(&mut self, header: &str) {
+    pub fn write_header(&mut self, header: &str) -> io::Result<()> {
         if let Some(writer) = &mut self.writer {
-            let _ = writer.write_all(header.as_bytes());
+            writer.write_all(header.as_bytes())?;
         }
+        Ok(())
     }
 
-    /// Flushes the buffer to the output.
-    pub fn flush(&mut self) {
+    /// Flushes the buffer to the output, including the line of the last
+    /// record at record level.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_record_line()?;
+        self.write_buffer()?;
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffer to the output.
+    fn write_buffer(&mut self) -> io::Result<()> {
         if let Some(writer) = &mut self.writer
             && !self.buffer.is_empty()
         {
-            let _ = writer.write_all(self.buffer.as_bytes());
+            writer.write_all(&self.buffer)?;
             self.buffer.clear();
         }
+        Ok(())
     }
 
-    /// Returns a reference to the collected records.
+    /// Returns the retained records; empty unless `keep_records` was set.
+    #[cfg(test)]
     pub fn records(&self) -> &[String] {
-        &self.records
+        self.records.as_deref().unwrap_or_default()
+    }
+}
+
+/// Layout of the JSON log.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonFormat {
+    /// A single pretty-printed JSON document.
+    Pretty,
+    /// JSON Lines (NDJSON): one compact object per hit and a final summary line.
+    Lines,
+}
+
+/// Returns the fields of a record ID for the JSON log. JSON strings have to
+/// be valid UTF-8, so other IDs are given with replacement characters in
+/// `record_id` and losslessly as hexadecimal bytes in `record_id_hex`.
+pub fn json_record_id(record: &[u8]) -> (String, Option<String>) {
+    match std::str::from_utf8(record) {
+        Ok(id) => (id.to_string(), None),
+        Err(_) => {
+            let hex = record.iter().map(|b| format!("{b:02x}")).collect();
+            (String::from_utf8_lossy(record).into_owned(), Some(hex))
+        }
+    }
+}
+
+/// Returns the summary sections of the JSON log as one object, as written to
+/// the last line of JSON Lines logs and to the metadata of hit tables.
+pub fn summary_json(
+    meta_information: &serde_json::Value,
+    pattern_hit_counts: &serde_json::Value,
+    summary_statistics: &serde_json::Value,
+    paired_end_stats: Option<&serde_json::Value>,
+    per_sample_stats: Option<&serde_json::Value>,
+) -> serde_json::Value {
+    let mut summary = json!({
+        "meta_information": meta_information,
+        "pattern_hit_counts": pattern_hit_counts,
+        "summary_statistics": summary_statistics,
+    });
+    if let Some(stats) = paired_end_stats {
+        summary["paired_end_reads_statistics"] = stats.clone();
+    }
+    if let Some(stats) = per_sample_stats {
+        summary["per_sample_statistics"] = stats.clone();
     }
+    summary
 }
 
 /// A logger that streams matching records directly to a JSON file.
@@ -88,39 +308,76 @@ pub struct JsonLogger {
     writer: Option<Box<dyn io::Write>>,
     buffer_size: usize,
     first: bool,
+    format: JsonFormat,
 }
 
 impl JsonLogger {
     /// Create a new `JsonLogger` with the given writer and buffer size.
-    pub fn new(mut writer: Option<Box<dyn io::Write>>, buffer_size: usize) -> Self {
-        if let Some(w) = &mut writer {
-            let _ = w.write_all(b"{\n  \"matching_records\": [\n");
+    #[cfg(test)]
+    pub fn new(writer: Option<Box<dyn io::Write>>, buffer_size: usize) -> io::Result<Self> {
+        Self::with_format(writer, buffer_size, JsonFormat::Pretty)
+    }
+
+    /// Create a new `JsonLogger` writing the given JSON layout.
+    pub fn with_format(
+        mut writer: Option<Box<dyn io::Write>>,
+        buffer_size: usize,
+        format: JsonFormat,
+    ) -> io::Result<Self> {
+        if let Some(w) = &mut writer
+            && format == JsonFormat::Pretty
+        {
+            w.write_all(b"{\n  \"matching_records\": [\n")?;
         }
-        Self {
+        Ok(Self {
             buffer: String::with_capacity(buffer_size),
             writer,
             buffer_size,
             first: true,
-        }
+            format,
+        })
     }
 
     /// Log record fields as a JSON object.
-    pub fn log_fields(&mut self, file: &str, record: &[u8], pattern: &str, index: usize) {
-        let id_str = str::from_utf8(record).expect("Error during id parsing.");
+    pub fn log_fields(&mut self, file: &str, record: &[u8], pattern: &str, index: usize) -> io::Result<()> {
+        let (id_str, id_hex) = json_record_id(record);
+
+        // One compact object per line, with a numeric position
+        if self.format == JsonFormat::Lines {
+            let mut value = json!({
+                "type": "hit",
+                "file": file,
+                "record_id": id_str,
+                "pattern": pattern,
+                "position": index,
+            });
+            if let Some(id_hex) = id_hex {
+                value["record_id_hex"] = json!(id_hex);
+            }
+            self.buffer.push_str(&value.to_string());
+            self.buffer.push('\n');
+            if self.buffer.len() >= self.buffer_size {
+                self.write_buffer()?;
+            }
+            return Ok(());
+        }
 
         if !self.first {
             self.buffer.push_str(",\n");
         }
         self.first = false;
 
-        let value = json!({
+        let mut value = json!({
             "file": file,
             "record_id": id_str,
             "pattern": pattern,
             "position": index.to_string(),
         });
+        if let Some(id_hex) = id_hex {
+            value["record_id_hex"] = json!(id_hex);
+        }
 
-        let pretty = serde_json::to_string_pretty(&value).unwrap();
+        let pretty = serde_json::to_string_pretty(&value)?;
         for line in pretty.lines() {
             self.buffer.push_str("    ");
             self.buffer.push_str(line);
@@ -128,23 +385,34 @@ impl JsonLogger {
         }
 
         if self.buffer.len() >= self.buffer_size {
-            self.flush();
+            self.write_buffer()?;
+        }
+        Ok(())
+    }
+
+    /// Flush the internal buffer and the output.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer()?;
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
         }
+        Ok(())
     }
 
-    /// Flush the internal buffer.
-    pub fn flush(&mut self) {
+    /// Write the internal buffer to the output.
+    fn write_buffer(&mut self) -> io::Result<()> {
         if let Some(writer) = &mut self.writer
             && !self.buffer.is_empty()
         {
-            let _ = writer.write_all(self.buffer.as_bytes());
+            writer.write_all(self.buffer.as_bytes())?;
             self.buffer.clear();
         }
+        Ok(())
     }
 
-    fn write_indented_value(&mut self, value: &serde_json::Value, indent: usize) {
+    fn write_indented_value(&mut self, value: &serde_json::Value, indent: usize) -> io::Result<()> {
         let indent_str = " ".repeat(indent);
-        let pretty = serde_json::to_string_pretty(value).unwrap();
+        let pretty = serde_json::to_string_pretty(value)?;
         for (i, line) in pretty.lines().enumerate() {
             if i > 0 {
                 self.buffer.push_str(&indent_str);
@@ -152,6 +420,7 @@ impl JsonLogger {
             self.buffer.push_str(line);
             self.buffer.push('\n');
         }
+        Ok(())
     }
 
     /// Finalize the JSON output by writing summary information.
@@ -161,33 +430,157 @@ impl JsonLogger {
         pattern_hit_counts: &serde_json::Value,
         summary_statistics: &serde_json::Value,
         paired_end_stats: Option<&serde_json::Value>,
-    ) {
+        per_sample_stats: Option<&serde_json::Value>,
+    ) -> io::Result<()> {
+        // The summary is written as a single last line
+        if self.format == JsonFormat::Lines {
+            let mut summary = summary_json(
+                meta_information,
+                pattern_hit_counts,
+                summary_statistics,
+                paired_end_stats,
+                per_sample_stats,
+            );
+            summary["type"] = json!("summary");
+            self.buffer.push_str(&summary.to_string());
+            self.buffer.push('\n');
+            return self.flush();
+        }
+
         self.buffer.push_str("  ],\n  \"meta_information\": ");
-        self.write_indented_value(meta_information, 2);
+        self.write_indented_value(meta_information, 2)?;
         if self.buffer.ends_with('\n') {
             self.buffer.pop();
         }
         if let Some(stats) = paired_end_stats {
             self.buffer
                 .push_str(",\n  \"paired_end_reads_statistics\": ");
-            self.write_indented_value(stats, 2);
+            self.write_indented_value(stats, 2)?;
             if self.buffer.ends_with('\n') {
                 self.buffer.pop();
             }
         }
         self.buffer.push_str(",\n  \"pattern_hit_counts\": ");
-        self.write_indented_value(pattern_hit_counts, 2);
+        self.write_indented_value(pattern_hit_counts, 2)?;
         if self.buffer.ends_with('\n') {
             self.buffer.pop();
         }
+        if let Some(stats) = per_sample_stats {
+            self.buffer.push_str(",\n  \"per_sample_statistics\": ");
+            self.write_indented_value(stats, 2)?;
+            if self.buffer.ends_with('\n') {
+                self.buffer.pop();
+            }
+        }
         self.buffer.push_str(",\n  \"summary_statistics\": ");
-        self.write_indented_value(summary_statistics, 2);
+        self.write_indented_value(summary_statistics, 2)?;
         if self.buffer.ends_with('\n') {
             self.buffer.pop();
         }
         self.buffer.push_str("\n}\n");
-        self.flush();
+        self.flush()
+    }
+}
+
+/// Format of the hit locations written by a `FeatureLogger`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureFormat {
+    /// BED6 with zero-based, half-open coordinates.
+    Bed,
+    /// GFF3 with one-based, closed coordinates.
+    Gff,
+}
+
+/// A logger that writes the locations of hits as BED or GFF features, e.g.
+/// to view them in a genome browser.
+pub struct FeatureLogger {
+    buffer: Vec<u8>,
+    writer: Box<dyn io::Write>,
+    buffer_size: usize,
+    format: FeatureFormat,
+}
+
+impl FeatureLogger {
+    /// Creates a new `FeatureLogger` with the given writer, format and buffer size.
+    pub fn new(mut writer: Box<dyn io::Write>, format: FeatureFormat, buffer_size: usize) -> io::Result<Self> {
+        if format == FeatureFormat::Gff {
+            writer.write_all(b"##gff-version 3\n")?;
+        }
+        Ok(Self {
+            buffer: Vec::with_capacity(buffer_size),
+            writer,
+            buffer_size,
+            format,
+        })
+    }
+
+    /// Logs a hit of `pattern` (named `name`) from `start` (zero-based) to
+    /// `end` (exclusive) on the given strand of a record. The sequence name
+    /// is the record id up to the first whitespace.
+    pub fn log_feature(
+        &mut self,
+        record: &[u8],
+        name: &str,
+        pattern: &str,
+        start: usize,
+        end: usize,
+        strand: char,
+    ) -> io::Result<()> {
+        let id = record
+            .split(|b| b.is_ascii_whitespace())
+            .next()
+            .unwrap_or_default();
+
+        match self.format {
+            FeatureFormat::Bed => {
+                self.buffer.extend_from_slice(id);
+                writeln!(self.buffer, "\t{start}\t{end}\t{name}\t0\t{strand}")?;
+            }
+            FeatureFormat::Gff => {
+                writeln!(
+                    self.buffer,
+                    "{}\tMerKurio\tnucleotide_match\t{}\t{end}\t.\t{strand}\t.\tName={};kmer={pattern}",
+                    gff_escape(id),
+                    start + 1,
+                    gff_escape(name.as_bytes()),
+                )?;
+            }
+        }
+
+        if self.buffer.len() >= self.buffer_size {
+            self.write_buffer()?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the buffer to the output.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer()?;
+        self.writer.flush()
+    }
+
+    fn write_buffer(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.writer.write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+/// Escapes the characters with a special meaning in GFF3 columns, control
+/// characters and non-ASCII bytes (so IDs that are not valid UTF-8 are kept
+/// losslessly).
+fn gff_escape(s: &[u8]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for &b in s {
+        match b {
+            b'%' | b';' | b'=' | b'&' | b',' => escaped.push_str(&format!("%{b:02X}")),
+            _ if b.is_ascii_control() || !b.is_ascii() => escaped.push_str(&format!("%{b:02X}")),
+            _ => escaped.push(b as char),
+        }
     }
+    escaped
 }
 
 #[cfg(test)]
@@ -196,17 +589,17 @@ mod tests {
 
     #[test]
     fn test_buffered_logger_basic() {
-        let mut logger = BufferedLogger::new(None, 1024);
+        let mut logger = BufferedLogger::new(None, 1024).keep_records();
 
         // Test header writing
-        logger.write_header("Header 1\n");
-        logger.write_header("Header 2\n");
-        logger.flush();
+        logger.write_header("Header 1\n").unwrap();
+        logger.write_header("Header 2\n").unwrap();
+        logger.flush().unwrap();
 
         // Test record logging
-        logger.log_record("Record 1\n");
-        logger.log_record("Record 2\n");
-        logger.flush();
+        logger.log_record("Record 1\n").unwrap();
+        logger.log_record("Record 2\n").unwrap();
+        logger.flush().unwrap();
 
         // Verify records are stored correctly
         let records = logger.records();
@@ -217,12 +610,12 @@ mod tests {
 
     #[test]
     fn test_buffered_logger_no_writer() {
-        let mut logger = BufferedLogger::new(None, 1024);
+        let mut logger = BufferedLogger::new(None, 1024).keep_records();
 
         // These should not panic
-        logger.write_header("Header\n");
-        logger.log_record("Record\n");
-        logger.flush();
+        logger.write_header("Header\n").unwrap();
+        logger.log_record("Record\n").unwrap();
+        logger.flush().unwrap();
 
         // Records should still be stored
         let records = logger.records();
@@ -232,21 +625,21 @@ mod tests {
 
     #[test]
     fn test_buffered_logger_empty() {
-        let mut logger = BufferedLogger::new(None, 1024);
+        let mut logger = BufferedLogger::new(None, 1024).keep_records();
 
         // Flush empty logger
-        logger.flush();
+        logger.flush().unwrap();
         assert_eq!(logger.records().len(), 0);
     }
 
     #[test]
     fn test_buffered_logger_records_only() {
-        let mut logger = BufferedLogger::new(None, 1024);
+        let mut logger = BufferedLogger::new(None, 1024).keep_records();
 
         // Log some records
-        logger.log_record("Record 1\n");
-        logger.log_record("Record 2\n");
-        logger.log_record("Record 3\n");
+        logger.log_record("Record 1\n").unwrap();
+        logger.log_record("Record 2\n").unwrap();
+        logger.log_record("Record 3\n").unwrap();
 
         // Check if records are stored correctly
         let records = logger.records();
@@ -255,4 +648,183 @@ mod tests {
         assert_eq!(records[1], "Record 2\n");
         assert_eq!(records[2], "Record 3\n");
     }
+
+    #[test]
+    fn test_feature_logger_bed_and_gff() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let bed_path = temp_dir.path().join("hits.bed");
+        let gff_path = temp_dir.path().join("hits.gff");
+
+        let bed_file = std::fs::File::create(&bed_path).unwrap();
+        let mut logger = FeatureLogger::new(Box::new(bed_file), FeatureFormat::Bed, 1024).unwrap();
+        logger.log_feature(b"chr1 description", "kmer1", "ACGT", 10, 14, '+').unwrap();
+        logger.flush().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&bed_path).unwrap(),
+            "chr1\t10\t14\tkmer1\t0\t+\n"
+        );
+
+        let gff_file = std::fs::File::create(&gff_path).unwrap();
+        let mut logger = FeatureLogger::new(Box::new(gff_file), FeatureFormat::Gff, 1024).unwrap();
+        logger.log_feature(b"chr1", "a;b", "ACGT", 10, 14, '-').unwrap();
+        logger.flush().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&gff_path).unwrap(),
+            "##gff-version 3\nchr1\tMerKurio\tnucleotide_match\t11\t14\t.\t-\t.\tName=a%3Bb;kmer=ACGT\n"
+        );
+    }
+
+    #[test]
+    fn test_json_logger_lines() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("log.jsonl");
+        let file = std::fs::File::create(&path).unwrap();
+        let mut logger = JsonLogger::with_format(Some(Box::new(file)), 1024, JsonFormat::Lines).unwrap();
+        logger.log_fields("reads.fq", b"read1", "ACGT", 3).unwrap();
+        logger.log_fields("reads.fq", b"read2", "ACGT", 0).unwrap();
+        logger.finalize(
+            &json!({"subcommand": "extract"}),
+            &json!({"ACGT": 2}),
+            &json!({"number_of_matches": 2}),
+            None,
+            None,
+        ).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            json!({"type": "hit", "file": "reads.fq", "record_id": "read1", "pattern": "ACGT", "position": 3})
+        );
+        assert_eq!(lines[2]["type"], "summary");
+        assert_eq!(lines[2]["summary_statistics"]["number_of_matches"], 2);
+        assert!(lines[2].get("paired_end_reads_statistics").is_none());
+    }
+
+    #[test]
+    fn test_buffered_logger_record_level() {
+        let mut logger = BufferedLogger::with_level(None, 1024, LogLevel::Record).keep_records();
+        logger.log_fields("reads.fq", b"read1", "ACG", 0).unwrap();
+        logger.log_fields("reads.fq", b"read1", "CGT", 1).unwrap();
+        logger.log_fields("reads.fq", b"read1", "ACG", 4).unwrap();
+        logger.log_fields("reads.fq", b"read2", "CGT", 7).unwrap();
+        logger.flush().unwrap();
+
+        assert_eq!(
+            logger.records(),
+            ["reads.fq\tread1\t3\tACG,CGT\t0,4;1\n", "reads.fq\tread2\t1\tCGT\t7\n"]
+        );
+    }
+
+    #[test]
+    fn test_buffered_logger_record_level_same_ids() {
+        // Mates of interleaved reads with the same ID are separate records
+        let mut logger = BufferedLogger::with_level(None, 1024, LogLevel::Record).keep_records();
+        logger.log_hit("reads.fq", b"read1", 0, "ACG", 0).unwrap();
+        logger.log_hit("reads.fq", b"read1", 0, "ACG", 4).unwrap();
+        logger.log_hit("reads.fq", b"read1", 1, "CGT", 1).unwrap();
+        logger.flush().unwrap();
+
+        assert_eq!(
+            logger.records(),
+            ["reads.fq\tread1\t2\tACG\t0,4\n", "reads.fq\tread1\t1\tCGT\t1\n"]
+        );
+    }
+
+    #[test]
+    fn test_buffered_logger_summary_level() {
+        let mut logger = BufferedLogger::with_level(None, 1024, LogLevel::Summary).keep_records();
+        logger.log_fields("reads.fq", b"read1", "ACG", 0).unwrap();
+        logger.flush().unwrap();
+        assert!(logger.records().is_empty());
+    }
+
+    #[test]
+    fn test_buffered_logger_constant_memory() {
+        let mut logger = BufferedLogger::new(None, 16);
+        for i in 0..1000 {
+            logger.log_fields("reads.fq", b"read", "ACGT", i).unwrap();
+        }
+        logger.flush().unwrap();
+
+        // Without a writer and retention, nothing is kept in memory
+        assert!(logger.records().is_empty());
+        assert!(logger.buffer.is_empty());
+    }
+
+    /// A writer that fails like a full disk.
+    struct FailingWriter;
+
+    impl io::Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("No space left on device"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_loggers_propagate_write_errors() {
+        let mut logger = BufferedLogger::new(Some(Box::new(FailingWriter)), 1024);
+        assert!(logger.write_header("#Header\n").is_err());
+        logger.log_fields("reads.fq", b"read1", "ACGT", 0).unwrap();
+        assert!(logger.flush().is_err());
+
+        assert!(JsonLogger::new(Some(Box::new(FailingWriter)), 1024).is_err());
+        let mut json_logger = JsonLogger::with_format(Some(Box::new(FailingWriter)), 1, JsonFormat::Lines).unwrap();
+        assert!(json_logger.log_fields("reads.fq", b"read1", "ACGT", 0).is_err());
+
+        assert!(FeatureLogger::new(Box::new(FailingWriter), FeatureFormat::Gff, 1024).is_err());
+    }
+
+    #[test]
+    fn test_loggers_non_utf8_record_ids() {
+        let id = b"read\xff1";
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        // Text log and BED keep the raw bytes
+        let log_path = temp_dir.path().join("log.txt");
+        let file = std::fs::File::create(&log_path).unwrap();
+        let mut logger = BufferedLogger::new(Some(Box::new(file)), 1024);
+        logger.log_fields("reads.fq", id, "ACGT", 2).unwrap();
+        logger.flush().unwrap();
+        assert_eq!(std::fs::read(&log_path).unwrap(), b"reads.fq\tread\xff1\tACGT\t2\n");
+
+        let bed_path = temp_dir.path().join("hits.bed");
+        let file = std::fs::File::create(&bed_path).unwrap();
+        let mut logger = FeatureLogger::new(Box::new(file), FeatureFormat::Bed, 1024).unwrap();
+        logger.log_feature(id, "kmer1", "ACGT", 2, 6, '+').unwrap();
+        logger.flush().unwrap();
+        assert_eq!(std::fs::read(&bed_path).unwrap(), b"read\xff1\t2\t6\tkmer1\t0\t+\n");
+
+        // GFF percent-encodes the byte
+        let gff_path = temp_dir.path().join("hits.gff");
+        let file = std::fs::File::create(&gff_path).unwrap();
+        let mut logger = FeatureLogger::new(Box::new(file), FeatureFormat::Gff, 1024).unwrap();
+        logger.log_feature(id, "kmer1", "ACGT", 2, 6, '+').unwrap();
+        logger.flush().unwrap();
+        assert!(std::fs::read_to_string(&gff_path).unwrap().contains("\nread%FF1\t"));
+
+        // JSON has the lossy ID and the raw bytes in hex
+        let json_path = temp_dir.path().join("log.jsonl");
+        let file = std::fs::File::create(&json_path).unwrap();
+        let mut logger = JsonLogger::with_format(Some(Box::new(file)), 1024, JsonFormat::Lines).unwrap();
+        logger.log_fields("reads.fq", id, "ACGT", 2).unwrap();
+        logger.log_fields("reads.fq", b"read2", "ACGT", 0).unwrap();
+        logger.flush().unwrap();
+        let content = std::fs::read_to_string(&json_path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["record_id"], "read\u{fffd}1");
+        assert_eq!(lines[0]["record_id_hex"], "72656164ff31");
+        assert!(lines[1].get("record_id_hex").is_none());
+    }
 }
@@ -1,12 +1,6 @@
-pub mod cmd_extract;
-pub mod cmd_tag;
-pub mod helpers;
-pub mod logger;
-pub mod pattern_matching;
-pub mod pattern_preprocessing;
-
 use anyhow::Result;
-use clap::{Parser, Subcommand, crate_authors, crate_version};
+use merkurio::{CmdExtract, CmdTag, expand_config_args, extract_records, tag_records};
+use clap::{CommandFactory, Parser, Subcommand, crate_authors, crate_version};
 
 use std::env;
 use std::str;
@@ -32,24 +26,31 @@ enum Commands {
         long_about = "Search for query sequences (k-mers) in FASTA/FASTQ files and extract records containing the patterns. It can also generate a log file with match statistics in plain text or JSON format. Paired-end reads can be processed together.",
         arg_required_else_help = true
     )]
-    Extract(cmd_extract::CmdExtract),
+    Extract(CmdExtract),
     #[clap(
         name = "tag",
         about = "Tag records in a BAM/SAM file with the presence of query sequences",
         long_about = "Tag and filter records in a BAM/SAM file with the presence of query sequences by using a SAM optional tag (default 'km'). Optionally, keep only those records with/without matching k-mers. It can also generate a log file with match statistics in plain text or JSON format.",
         arg_required_else_help = true
     )]
-    Tag(cmd_tag::CmdTag),
+    Tag(CmdTag),
 }
 
 fn main() -> Result<()> {
-    // Parse command line arguments
-    let args_parsed = Cli::parse();
+    // Add the options of a configuration file and parse command line arguments
+    let (argv, config_values) = expand_config_args(&Cli::command(), env::args_os().collect())?;
+    let args_parsed = Cli::parse_from(argv);
 
     // Call subcommand function
     match args_parsed.cmd {
-        Commands::Extract(args) => cmd_extract::extract_records(args),
-        Commands::Tag(args) => cmd_tag::tag_records(args),
+        Commands::Extract(mut args) => {
+            args.config_values = config_values;
+            extract_records(args)
+        }
+        Commands::Tag(mut args) => {
+            args.config_values = config_values;
+            tag_records(args)
+        }
     }
 }
 
@@ -58,6 +59,7 @@ fn main() -> Result<()> {
 //
 
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests {
     use super::*;
     use clap::crate_name;
@@ -290,4 +292,103 @@ mod tests {
         );
         assert!(args.is_ok());
     }
+
+    #[test]
+    #[should_panic]
+    fn test_cli_parser_pair_mode_requires_paired() {
+        let args = Cli::try_parse_from(
+            [
+                crate_name!(),
+                "extract",
+                "-i",
+                "tests/data/sample.fasta",
+                "--kmer-seq",
+                "ACGT",
+                "--pair-mode",
+                "both",
+            ]
+            .iter(),
+        );
+        assert!(args.is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_cli_parser_interleaved_conflicts_paired() {
+        let args = Cli::try_parse_from(
+            [
+                crate_name!(),
+                "extract",
+                "-i",
+                "tests/data/sample_1.fastq",
+                "-2",
+                "tests/data/sample_2.fastq",
+                "--interleaved",
+                "--kmer-seq",
+                "ACGT",
+            ]
+            .iter(),
+        );
+        assert!(args.is_ok());
+    }
+
+    #[test]
+    fn test_cli_parser_multiple_inputs_extract() {
+        let args = Cli::try_parse_from(
+            [
+                crate_name!(),
+                "extract",
+                "-i",
+                "tests/data/a_1.fastq",
+                "tests/data/b_1.fastq",
+                "-2",
+                "tests/data/a_2.fastq",
+                "tests/data/b_2.fastq",
+                "--kmer-seq",
+                "ACGT",
+                "-o",
+                "out",
+            ]
+            .iter(),
+        );
+        assert!(args.is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_cli_parser_sample_sheet_conflicts_input() {
+        let args = Cli::try_parse_from(
+            [
+                crate_name!(),
+                "extract",
+                "-i",
+                "tests/data/sample_1.fastq",
+                "--sample-sheet",
+                "samples.tsv",
+                "--kmer-seq",
+                "ACGT",
+            ]
+            .iter(),
+        );
+        assert!(args.is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_cli_parser_count_unit_requires_count_matrix() {
+        let args = Cli::try_parse_from(
+            [
+                crate_name!(),
+                "extract",
+                "-i",
+                "tests/data/sample_1.fastq",
+                "--count-unit",
+                "occurrences",
+                "--kmer-seq",
+                "ACGT",
+            ]
+            .iter(),
+        );
+        assert!(args.is_ok());
+    }
 }
@@ -10,18 +10,19 @@
 //! # Example
 //!
 //! ```
-//! use pattern_matching::BNDMq;
+//! use merkurio::pattern_matching::BNDMq;
 //!
 //! let pattern = b"abc";
 //! let text = b"abcabcabc";
-//!     
+//!
 //! let bndmq = BNDMq::new(pattern, 2).unwrap();
 //!
-//! let matches: Vec<usize> = bndmq.find_all(text).collect();
+//! let matches: Vec<usize> = bndmq.find_all(text);
 //! assert_eq!(matches, vec![0, 3, 6]);
 //! ```
 
 use crate::pattern_preprocessing::generate_masks;
+use aho_corasick::{AhoCorasick, AhoCorasickKind, MatchKind};
 use anyhow::Result;
 
 /// Error type for pattern matching operations
@@ -341,6 +342,144 @@ impl BNDM {
     }
 }
 
+/// A matcher searching for several patterns at once. Occurrences are given
+/// as `(pattern_id, start, end)`, with the index of the pattern in the list
+/// the matcher was built from and the range `[start, end)` of the hit.
+///
+/// Each search engine implements this trait, so the subcommands have a
+/// single code path for all of them.
+pub trait MultiPatternMatcher: Send + Sync {
+    /// Returns an iterator over all occurrences of all patterns in the text,
+    /// including overlapping ones. The order of the occurrences depends on
+    /// the engine.
+    fn find_iter<'a>(&'a self, text: &'a [u8]) -> Box<dyn Iterator<Item = (usize, usize, usize)> + 'a>;
+
+    /// Returns true if any of the patterns occurs in the text.
+    fn find_any(&self, text: &[u8]) -> bool {
+        self.find_iter(text).next().is_some()
+    }
+
+    /// Returns the number of occurrences of all patterns in the text.
+    fn count(&self, text: &[u8]) -> usize {
+        self.find_iter(text).count()
+    }
+}
+
+/// An Aho-Corasick automaton with the standard match semantics, the only
+/// ones supporting overlapping searches.
+#[derive(Debug, Clone)]
+pub struct AhoCorasickMatcher {
+    ac: AhoCorasick,
+}
+
+impl AhoCorasickMatcher {
+    /// Builds a DFA-based automaton for the patterns, optionally matching
+    /// ASCII letters case-insensitively.
+    pub fn new(patterns: &[String], case_insensitive: bool) -> Result<Self> {
+        let ac = AhoCorasick::builder()
+            // Use DFA for better search performance at higher memory cost
+            .kind(Some(AhoCorasickKind::DFA))
+            .match_kind(MatchKind::Standard)
+            .ascii_case_insensitive(case_insensitive)
+            .build(patterns)?;
+        Ok(Self { ac })
+    }
+}
+
+impl MultiPatternMatcher for AhoCorasickMatcher {
+    fn find_iter<'a>(&'a self, text: &'a [u8]) -> Box<dyn Iterator<Item = (usize, usize, usize)> + 'a> {
+        Box::new(
+            self.ac
+                .find_overlapping_iter(text)
+                .map(|mat| (mat.pattern().as_usize(), mat.start(), mat.end())),
+        )
+    }
+
+    fn find_any(&self, text: &[u8]) -> bool {
+        self.ac.is_match(text)
+    }
+}
+
+/// One BNDMq instance per pattern.
+#[derive(Debug, Clone)]
+pub struct BNDMqMatcher {
+    matchers: Vec<BNDMq>,
+}
+
+impl BNDMqMatcher {
+    /// Creates a BNDMq instance for each pattern, with the given _q_-gram
+    /// length or one tuned to the pattern length.
+    pub fn new(patterns: &[String], q: Option<usize>) -> Result<Self> {
+        let matchers = patterns
+            .iter()
+            .map(|pattern| {
+                let q = match q {
+                    Some(q) => q,
+                    None => tune_q_value(pattern)?,
+                };
+                Ok(BNDMq::new(pattern.as_bytes(), q)?)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { matchers })
+    }
+}
+
+impl MultiPatternMatcher for BNDMqMatcher {
+    fn find_iter<'a>(&'a self, text: &'a [u8]) -> Box<dyn Iterator<Item = (usize, usize, usize)> + 'a> {
+        Box::new(self.matchers.iter().enumerate().flat_map(move |(id, bndmq)| {
+            bndmq.find_iter(text).map(move |start| (id, start, start + bndmq.m))
+        }))
+    }
+
+    fn find_any(&self, text: &[u8]) -> bool {
+        self.matchers.iter().any(|bndmq| bndmq.find_match(text))
+    }
+}
+
+/// One BNDM instance per pattern.
+#[derive(Debug, Clone)]
+pub struct BNDMMatcher {
+    matchers: Vec<BNDM>,
+}
+
+impl BNDMMatcher {
+    /// Creates a BNDM instance for each pattern.
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let matchers = patterns
+            .iter()
+            .map(|pattern| BNDM::new(pattern.as_bytes()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { matchers })
+    }
+}
+
+impl MultiPatternMatcher for BNDMMatcher {
+    fn find_iter<'a>(&'a self, text: &'a [u8]) -> Box<dyn Iterator<Item = (usize, usize, usize)> + 'a> {
+        Box::new(self.matchers.iter().enumerate().flat_map(move |(id, bndm)| {
+            bndm.find_all(text).into_iter().map(move |start| (id, start, start + bndm.m))
+        }))
+    }
+
+    fn find_any(&self, text: &[u8]) -> bool {
+        self.matchers.iter().any(|bndm| bndm.find_match(text))
+    }
+}
+
+/// Builds the matcher for a list of patterns: an Aho-Corasick automaton if
+/// requested (always used for case-insensitive search), or BNDMq otherwise.
+pub fn build_matcher(
+    patterns: &[String],
+    aho_corasick: bool,
+    case_insensitive: bool,
+    q: Option<usize>,
+) -> Result<Box<dyn MultiPatternMatcher>> {
+    if aho_corasick || case_insensitive {
+        Ok(Box::new(AhoCorasickMatcher::new(patterns, case_insensitive)?))
+    } else {
+        Ok(Box::new(BNDMqMatcher::new(patterns, q)?))
+    }
+}
+
 //
 // ---------------------------------- Tests ----------------------------------
 //
@@ -486,4 +625,38 @@ mod tests {
         let q = tune_q_value("AAAAAAAACCCCCCCCGGGGGGGGTTTTTTT").unwrap();
         assert_eq!(q, 5);
     }
+
+    #[test]
+    fn test_multi_pattern_matchers() {
+        let patterns = vec!["AA".to_string(), "AAA".to_string(), "CGT".to_string()];
+        let text = b"AAAACGTT";
+        let matchers: Vec<Box<dyn MultiPatternMatcher>> = vec![
+            build_matcher(&patterns, true, false, None).unwrap(),
+            build_matcher(&patterns, false, false, None).unwrap(),
+            Box::new(BNDMMatcher::new(&patterns).unwrap()),
+        ];
+        for matcher in matchers {
+            let mut occurrences: Vec<(usize, usize, usize)> = matcher.find_iter(text).collect();
+            occurrences.sort_unstable();
+            assert_eq!(occurrences, [(0, 0, 2), (0, 1, 3), (0, 2, 4), (1, 0, 3), (1, 1, 4), (2, 4, 7)]);
+            assert_eq!(matcher.count(text), 6);
+            assert!(matcher.find_any(text));
+            assert!(!matcher.find_any(b"GGGG"));
+        }
+    }
+
+    #[test]
+    fn test_build_matcher_case_insensitive() {
+        let matcher = build_matcher(&["acg".to_string()], false, true, None).unwrap();
+        assert_eq!(matcher.find_iter(b"TACGT").collect::<Vec<_>>(), [(0, 1, 4)]);
+    }
+
+    #[test]
+    fn test_aho_corasick_matcher_overlapping() {
+        // Patterns that are prefixes of each other must all be reported
+        let matcher = AhoCorasickMatcher::new(&["AC".to_string(), "ACG".to_string()], false).unwrap();
+        let mut occurrences: Vec<_> = matcher.find_iter(b"ACGACG").collect();
+        occurrences.sort_unstable();
+        assert_eq!(occurrences, [(0, 0, 2), (0, 3, 5), (1, 0, 3), (1, 3, 6)]);
+    }
 }
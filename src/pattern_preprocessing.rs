@@ -6,10 +6,10 @@
 //! # Example
 //!
 //! ```
-//! use pattern_preprocessing::generate_masks;
+//! use merkurio::pattern_preprocessing::generate_masks;
 //!
 //! let pattern = b"abc";
-//! let (masks, accept) = generate_masks(pattern)?;
+//! let (masks, accept) = generate_masks(pattern).unwrap();
 //! assert_eq!(masks[97], 4); // 'a' bitmask
 //! assert_eq!(accept, 4); // accept state
 //! ```
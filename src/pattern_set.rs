@@ -0,0 +1,197 @@
+//! # Pattern sets for embedding the search in other tools.
+//!
+//! A `PatternSet` holds the preprocessed patterns (k-mers) to search for, read
+//! from a file or given as sequences, in the same way as the `-f`/`-s` options
+//! of the subcommands. The matcher for the set is chosen like on the command
+//! line, unless the algorithm is set explicitly.
+//!
+//! ```
+//! use merkurio::PatternSet;
+//!
+//! let patterns = PatternSet::builder()
+//!     .sequences(["ACG", "TTG"])
+//!     .reverse_complement(true)
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(patterns.patterns(), ["ACG", "CAA", "CGT", "TTG"]);
+//!
+//! let matcher = patterns.matcher().unwrap();
+//! assert_eq!(matcher.count(b"AACGTTG"), 3);
+//! ```
+
+use crate::helpers::{parse_pattern_list, recommend_aho_corasick};
+use crate::pattern_matching::{MultiPatternMatcher, build_matcher};
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// A sorted list of unique, non-empty patterns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternSet {
+    patterns: Vec<String>,
+}
+
+impl PatternSet {
+    /// Returns a builder for a pattern set.
+    pub fn builder() -> PatternSetBuilder {
+        PatternSetBuilder::default()
+    }
+
+    /// Returns the patterns, sorted. The index of a pattern in this list is
+    /// the pattern id reported by the matchers and used in the statistics.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Builds a matcher for the patterns, choosing the algorithm based on the
+    /// number and length of the patterns.
+    pub fn matcher(&self) -> Result<Box<dyn MultiPatternMatcher>> {
+        build_matcher(&self.patterns, recommend_aho_corasick(&self.patterns)?, false, None)
+    }
+
+    /// Builds a matcher for the patterns with the given algorithm. The
+    /// Aho-Corasick algorithm is always used for case-insensitive search,
+    /// and BNDMq tunes the _q_-gram length if `q` is not given.
+    pub fn matcher_with(
+        &self,
+        aho_corasick: bool,
+        case_insensitive: bool,
+        q: Option<usize>,
+    ) -> Result<Box<dyn MultiPatternMatcher>> {
+        build_matcher(&self.patterns, aho_corasick, case_insensitive, q)
+    }
+}
+
+/// Builder of a `PatternSet`, with the preprocessing options of the
+/// subcommands. A k-mer file takes precedence over sequences.
+#[derive(Debug, Clone, Default)]
+pub struct PatternSetBuilder {
+    kmer_file: Option<PathBuf>,
+    kmer_seq: Option<Vec<String>>,
+    reverse_complement: bool,
+    canonical: bool,
+    lowercase: bool,
+    uppercase: bool,
+}
+
+impl PatternSetBuilder {
+    /// Reads the patterns from a file (FASTA or one k-mer per line).
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.kmer_file = Some(path.into());
+        self
+    }
+
+    /// Adds patterns given as sequences.
+    pub fn sequences<I, S>(mut self, sequences: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.kmer_seq
+            .get_or_insert_with(Vec::new)
+            .extend(sequences.into_iter().map(Into::into));
+        self
+    }
+
+    /// Also searches for the reverse complements of the patterns.
+    pub fn reverse_complement(mut self, yes: bool) -> Self {
+        self.reverse_complement = yes;
+        self
+    }
+
+    /// Uses only the canonical forms of the patterns.
+    pub fn canonical(mut self, yes: bool) -> Self {
+        self.canonical = yes;
+        self
+    }
+
+    /// Converts the patterns to lowercase.
+    pub fn lowercase(mut self, yes: bool) -> Self {
+        self.lowercase = yes;
+        self
+    }
+
+    /// Converts the patterns to uppercase.
+    pub fn uppercase(mut self, yes: bool) -> Self {
+        self.uppercase = yes;
+        self
+    }
+
+    /// Reads and preprocesses the patterns. Returns an error if no patterns
+    /// are left, or if the options conflict.
+    pub fn build(self) -> Result<PatternSet> {
+        if self.reverse_complement && self.canonical {
+            anyhow::bail!("Reverse complements and canonical k-mers cannot be used together.");
+        }
+        if self.lowercase && self.uppercase {
+            anyhow::bail!("Patterns cannot be converted to both lowercase and uppercase.");
+        }
+        let patterns = parse_pattern_list(
+            &self.kmer_file,
+            self.kmer_seq,
+            self.reverse_complement,
+            self.canonical,
+            self.lowercase,
+            self.uppercase,
+        )?;
+        Ok(PatternSet { patterns })
+    }
+}
+
+//
+// ---------------------------------- Tests ----------------------------------
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_set_builder() {
+        let patterns = PatternSet::builder()
+            .sequences(["acg", "", "ACG"])
+            .sequences(vec!["TTT".to_string()])
+            .uppercase(true)
+            .build()
+            .unwrap();
+        assert_eq!(patterns.patterns(), ["ACG", "TTT"]);
+        assert_eq!(patterns.len(), 2);
+
+        let from_file = PatternSet::builder()
+            .file("tests/data/kmers.txt")
+            .sequences(["ACG"])
+            .build()
+            .unwrap();
+        assert_eq!(from_file.len(), 3);
+        assert!(!from_file.patterns().contains(&"ACG".to_string()));
+
+        assert!(PatternSet::builder().build().is_err());
+        assert!(PatternSet::builder().sequences([""]).build().is_err());
+        assert!(
+            PatternSet::builder()
+                .sequences(["ACG"])
+                .reverse_complement(true)
+                .canonical(true)
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_pattern_set_matchers() {
+        let patterns = PatternSet::builder().sequences(["CGT", "AC"]).build().unwrap();
+        let text = b"ACGTACGT";
+        let mut hits: Vec<_> = patterns.matcher().unwrap().find_iter(text).collect();
+        hits.sort_unstable();
+        assert_eq!(hits, [(0, 0, 2), (0, 4, 6), (1, 1, 4), (1, 5, 8)]);
+        let ac = patterns.matcher_with(true, true, None).unwrap();
+        assert_eq!(ac.count(b"acgt"), 2);
+    }
+}
@@ -0,0 +1,528 @@
+//! # Hits found in the records and their statistics.
+//!
+//! Both search algorithms, Aho-Corasick and BNDMq, report the hits of a record
+//! in the same order, as a stream of `HitEvent`s. All statistics are derived
+//! from these events, so they do not depend on the algorithm used:
+//!
+//! - occurrences: every (possibly overlapping) hit of a pattern,
+//! - records: the records with at least one hit of a pattern,
+//! - pairs: the read pairs with at least one hit of a pattern in either read,
+//! - forward and reverse complement hits: the occurrences of a pattern as a
+//!   k-mer or as the reverse complement of one, if the reverse complements
+//!   were searched (`-r`); they add up to the occurrences.
+
+use crate::helpers::{PatternOrigin, QualityFilter, RecordFilter};
+use crate::pattern_matching::MultiPatternMatcher;
+
+/// A hit of a pattern in a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HitEvent {
+    /// Index of the pattern in the pattern list.
+    pub pattern_idx: usize,
+    /// Zero-based start position of the hit.
+    pub pos: usize,
+    /// Length of the sequence of the record.
+    pub seq_len: usize,
+    /// Running number of the record among all records searched.
+    pub record_nb: usize,
+    /// Running number of the read pair, for paired-end reads.
+    pub pair_nb: Option<usize>,
+}
+
+/// Finds the hits in a sequence that pass the quality filter, as pairs of
+/// position and pattern index. The hits replace the content of `hits` and are
+/// sorted by position and then by pattern index, independently of the
+/// matcher. Returns the number of hits rejected for low quality.
+pub fn find_hits(
+    matcher: &dyn MultiPatternMatcher,
+    quality_filter: &QualityFilter,
+    seq: &[u8],
+    qual: Option<&[u8]>,
+    qual_offset: u8,
+    hits: &mut Vec<(usize, usize)>,
+) -> usize {
+    hits.clear();
+    let mut nb_rejected = 0;
+    for (idx, start, end) in matcher.find_iter(seq) {
+        if quality_filter.passes(qual, qual_offset, start, end - start) {
+            hits.push((start, idx));
+        } else {
+            nb_rejected += 1;
+        }
+    }
+    hits.sort_unstable();
+    nb_rejected
+}
+
+/// Searches records for the hits of a matcher that pass the filters, and
+/// counts the records and their hits, in the same way for the subcommands
+/// and the Python bindings.
+#[derive(Clone, Copy)]
+pub struct RecordSearch<'a> {
+    pub matcher: &'a dyn MultiPatternMatcher,
+    pub quality_filter: QualityFilter,
+    pub record_filter: RecordFilter,
+    /// Offset of the quality scores: 33 in FASTQ files, 0 in BAM/SAM records.
+    pub qual_offset: u8,
+}
+
+impl RecordSearch<'_> {
+    /// Searches a record and counts it and its hits in `stats`, unless it
+    /// fails the record filter, which is counted as a skipped record. The hits
+    /// replace the content of `hits`, sorted like by `find_hits`. Returns the
+    /// running number of the record, as in its `HitEvent`s, or `None` if it
+    /// was skipped.
+    pub fn search(
+        &self,
+        stats: &mut SearchStats,
+        seq: &[u8],
+        qual: Option<&[u8]>,
+        pair_nb: Option<usize>,
+        hits: &mut Vec<(usize, usize)>,
+    ) -> Option<usize> {
+        if !self.record_filter.passes(seq) {
+            hits.clear();
+            stats.nb_records_skipped += 1;
+            return None;
+        }
+        let record_nb = stats.nb_records_tot;
+        stats.nb_hits_rejected += find_hits(self.matcher, &self.quality_filter, seq, qual, self.qual_offset, hits);
+        for &(pos, pattern_idx) in hits.iter() {
+            stats.patterns.add_hit(&HitEvent {
+                pattern_idx,
+                pos,
+                seq_len: seq.len(),
+                record_nb,
+                pair_nb,
+            });
+        }
+        stats.nb_records_tot += 1;
+        stats.nb_bases += seq.len();
+        stats.nb_hits_tot += hits.len();
+        stats.nb_records_hit += !hits.is_empty() as usize;
+        Some(record_nb)
+    }
+
+    /// Returns true if any hit in the sequence passes the quality filter,
+    /// stopping at the first one. Neither the record filter is applied nor
+    /// anything counted.
+    pub fn has_hit(&self, seq: &[u8], qual: Option<&[u8]>) -> bool {
+        if self.quality_filter.is_active() {
+            self.matcher
+                .find_iter(seq)
+                .any(|(_, start, end)| self.quality_filter.passes(qual, self.qual_offset, start, end - start))
+        } else {
+            self.matcher.find_any(seq)
+        }
+    }
+}
+
+/// Counters of the records searched by a `RecordSearch` and of their hits.
+#[derive(Debug, Clone, Default)]
+pub struct SearchStats {
+    pub nb_records_tot: usize,
+    pub nb_bases: usize,
+    pub nb_hits_tot: usize,
+    pub nb_records_hit: usize,
+    pub nb_hits_rejected: usize,
+    pub nb_records_skipped: usize,
+    pub patterns: PatternStats,
+}
+
+impl SearchStats {
+    pub fn new(patterns: PatternStats) -> Self {
+        Self {
+            patterns,
+            ..Default::default()
+        }
+    }
+
+    /// Add the counts of another set of statistics to these.
+    pub fn merge(&mut self, other: &SearchStats) {
+        self.nb_records_tot += other.nb_records_tot;
+        self.nb_bases += other.nb_bases;
+        self.nb_hits_tot += other.nb_hits_tot;
+        self.nb_records_hit += other.nb_records_hit;
+        self.nb_hits_rejected += other.nb_hits_rejected;
+        self.nb_records_skipped += other.nb_records_skipped;
+        self.patterns.merge(&other.patterns);
+    }
+
+    /// The `summary_statistics` section of the JSON log. The counts of the
+    /// filters are `null` unless they are active, and the pattern statistics
+    /// are those of `PatternStats::to_json`.
+    pub fn summary_json(
+        &self,
+        pattern_list: &[String],
+        quality_filter_active: bool,
+        record_filter_active: bool,
+        paired: bool,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "number_of_patterns_searched": pattern_list.len(),
+            "number_of_patterns_found": self.patterns.nb_patterns_found(),
+            "number_of_records_searched": self.nb_records_tot,
+            "number_of_characters_searched": self.nb_bases,
+            "number_of_matches": self.nb_hits_tot,
+            "number_of_distinct_records_with_a_hit": self.nb_records_hit,
+            "number_of_hits_rejected_for_quality": if quality_filter_active { Some(self.nb_hits_rejected) } else { None },
+            "number_of_records_skipped_by_filters": if record_filter_active { Some(self.nb_records_skipped) } else { None },
+            "pattern_statistics": self.patterns.to_json(pattern_list, paired),
+        })
+    }
+}
+
+/// Number of bins of the position histograms, each covering a tenth of the
+/// sequence length.
+pub const POSITION_BINS: usize = 10;
+
+/// Per-pattern counters of the hits: occurrences, records and pairs with a
+/// hit, and a histogram of the hit positions along the sequences.
+#[derive(Debug, Clone, Default)]
+pub struct PatternStats {
+    pub occurrences: Vec<u32>,
+    pub records: Vec<u32>,
+    pub pairs: Vec<u32>,
+    pub position_histograms: Vec<[u32; POSITION_BINS]>,
+    /// Occurrences of each pattern as a k-mer and as the reverse complement
+    /// of one, counted only for statistics created with `with_origins`.
+    pub forward_hits: Vec<u32>,
+    pub reverse_complement_hits: Vec<u32>,
+    // Number of the last record and pair with a hit for each pattern, to
+    // count records and pairs with several occurrences of a pattern only once
+    last_hit_record: Vec<Option<usize>>,
+    last_hit_pair: Vec<Option<usize>>,
+    // For each pattern, whether it is a k-mer as given (forward strand)
+    strands: Option<Vec<bool>>,
+}
+
+impl PatternStats {
+    pub fn new(nb_patterns: usize) -> Self {
+        Self {
+            occurrences: vec![0; nb_patterns],
+            records: vec![0; nb_patterns],
+            pairs: vec![0; nb_patterns],
+            position_histograms: vec![[0; POSITION_BINS]; nb_patterns],
+            forward_hits: vec![0; nb_patterns],
+            reverse_complement_hits: vec![0; nb_patterns],
+            last_hit_record: vec![None; nb_patterns],
+            last_hit_pair: vec![None; nb_patterns],
+            strands: None,
+        }
+    }
+
+    /// Creates statistics that also count the hits of each pattern on the
+    /// forward and reverse strand, given the origin of each pattern as
+    /// recorded when the reverse complements were added (see
+    /// `parse_pattern_list_with_origins`). Hits of a pattern given as a k-mer
+    /// are forward hits, even if it is also the reverse complement of a
+    /// k-mer (e.g. a palindrome), like the strand of the BED/GFF output.
+    pub fn with_origins(origins: &[PatternOrigin]) -> Self {
+        Self {
+            strands: Some(origins.iter().map(|origin| origin.forward).collect()),
+            ..Self::new(origins.len())
+        }
+    }
+
+    /// Count a hit.
+    pub fn add_hit(&mut self, hit: &HitEvent) {
+        let idx = hit.pattern_idx;
+        self.occurrences[idx] += 1;
+        if self.last_hit_record[idx] != Some(hit.record_nb) {
+            self.last_hit_record[idx] = Some(hit.record_nb);
+            self.records[idx] += 1;
+        }
+        if hit.pair_nb.is_some() && self.last_hit_pair[idx] != hit.pair_nb {
+            self.last_hit_pair[idx] = hit.pair_nb;
+            self.pairs[idx] += 1;
+        }
+        let bin = (hit.pos * POSITION_BINS / hit.seq_len.max(1)).min(POSITION_BINS - 1);
+        self.position_histograms[idx][bin] += 1;
+        if let Some(strands) = &self.strands {
+            if strands[idx] {
+                self.forward_hits[idx] += 1;
+            } else {
+                self.reverse_complement_hits[idx] += 1;
+            }
+        }
+    }
+
+    /// Add the counts of another set of statistics to these.
+    pub fn merge(&mut self, other: &PatternStats) {
+        for (counts, other_counts) in [
+            (&mut self.occurrences, &other.occurrences),
+            (&mut self.records, &other.records),
+            (&mut self.pairs, &other.pairs),
+            (&mut self.forward_hits, &other.forward_hits),
+            (&mut self.reverse_complement_hits, &other.reverse_complement_hits),
+        ] {
+            for (count, other_count) in counts.iter_mut().zip(other_counts) {
+                *count += other_count;
+            }
+        }
+        for (histogram, other_histogram) in self.position_histograms.iter_mut().zip(&other.position_histograms) {
+            for (count, other_count) in histogram.iter_mut().zip(other_histogram) {
+                *count += other_count;
+            }
+        }
+        if self.strands.is_none() {
+            self.strands.clone_from(&other.strands);
+        }
+    }
+
+    /// Number of patterns with at least one hit.
+    pub fn nb_patterns_found(&self) -> usize {
+        self.occurrences.iter().filter(|&&count| count > 0).count()
+    }
+
+    /// Mean number of hits per record with a hit of the pattern.
+    pub fn mean_hits_per_record(&self, pattern_idx: usize) -> f64 {
+        match self.records[pattern_idx] {
+            0 => 0.0,
+            records => self.occurrences[pattern_idx] as f64 / records as f64,
+        }
+    }
+
+    /// Number of forward and reverse complement hits of a pattern, adding up
+    /// to its occurrences, or `None` if the strands are not counted.
+    pub fn strand_hits(&self, pattern_idx: usize) -> Option<(u32, u32)> {
+        self.strands.as_ref().map(|_| {
+            (self.forward_hits[pattern_idx], self.reverse_complement_hits[pattern_idx])
+        })
+    }
+
+    /// Table of the statistics for the footer of the plain text log. The
+    /// number of pairs with a hit is only listed for paired-end reads, and
+    /// the forward and reverse complement hits are NA unless the strands
+    /// are counted (see `strand_hits`).
+    pub fn to_text(&self, pattern_list: &[String], paired: bool) -> String {
+        let mut text = format!(
+            "#\n#Pattern statistics (position histogram in bins of 10 % of the sequence length)\n\
+             #Pattern\tOccurrences\tRecords\t{}Mean hits per record\tForward hits\tReverse complement hits\tPosition histogram\n",
+            if paired { "Pairs\t" } else { "" },
+        );
+        for (idx, pattern) in pattern_list.iter().enumerate() {
+            let pairs = if paired { format!("{}\t", self.pairs[idx]) } else { String::new() };
+            let (forward, rev_compl) = match self.strand_hits(idx) {
+                Some((forward, rev_compl)) => (forward.to_string(), rev_compl.to_string()),
+                None => ("NA".to_string(), "NA".to_string()),
+            };
+            let histogram: Vec<String> = self.position_histograms[idx].iter().map(|c| c.to_string()).collect();
+            text.push_str(&format!(
+                "#{}\t{}\t{}\t{}{:.2}\t{}\t{}\t{}\n",
+                pattern,
+                self.occurrences[idx],
+                self.records[idx],
+                pairs,
+                self.mean_hits_per_record(idx),
+                forward,
+                rev_compl,
+                histogram.join(","),
+            ));
+        }
+        text
+    }
+
+    /// Statistics as a JSON object with an entry for each pattern. The
+    /// number of pairs with a hit is `null` unless reads are paired, and the
+    /// forward and reverse complement hits are `null` unless the strands are
+    /// counted.
+    pub fn to_json(&self, pattern_list: &[String], paired: bool) -> serde_json::Value {
+        let stats: serde_json::Map<String, serde_json::Value> = pattern_list
+            .iter()
+            .enumerate()
+            .map(|(idx, pattern)| {
+                (
+                    pattern.clone(),
+                    serde_json::json!({
+                        "occurrences": self.occurrences[idx],
+                        "records": self.records[idx],
+                        "pairs": if paired { Some(self.pairs[idx]) } else { None },
+                        "mean_hits_per_record": self.mean_hits_per_record(idx),
+                        "forward_hits": self.strand_hits(idx).map(|(forward, _)| forward),
+                        "reverse_complement_hits": self.strand_hits(idx).map(|(_, rev_compl)| rev_compl),
+                        "position_histogram": self.position_histograms[idx],
+                    }),
+                )
+            })
+            .collect();
+        serde_json::Value::Object(stats)
+    }
+
+    /// Occurrences of each pattern as a JSON object, for `pattern_hit_counts`.
+    pub fn occurrences_json(&self, pattern_list: &[String]) -> serde_json::Value {
+        pattern_list
+            .iter()
+            .cloned()
+            .zip(self.occurrences.iter().map(|&count| serde_json::json!(count)))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+}
+
+//
+// ---------------------------------- Tests ----------------------------------
+//
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pattern_matching::{BNDMqMatcher, build_matcher};
+
+    fn hit(pattern_idx: usize, record_nb: usize, pos: usize, seq_len: usize) -> HitEvent {
+        HitEvent {
+            pattern_idx,
+            pos,
+            seq_len,
+            record_nb,
+            pair_nb: Some(record_nb / 2),
+        }
+    }
+
+    #[test]
+    fn test_pattern_stats() {
+        let pattern_list = vec!["AAC".to_string(), "GTT".to_string(), "TTT".to_string()];
+        let mut stats = PatternStats::new(pattern_list.len());
+        stats.add_hit(&hit(0, 0, 0, 20));
+        stats.add_hit(&hit(0, 0, 19, 20));
+        stats.add_hit(&hit(0, 1, 5, 10));
+        stats.add_hit(&hit(1, 2, 1, 10));
+
+        let mut total = PatternStats::new(pattern_list.len());
+        total.merge(&stats);
+        assert_eq!(total.occurrences, stats.occurrences);
+        assert_eq!(total.position_histograms, stats.position_histograms);
+        assert_eq!(stats.occurrences, [3, 1, 0]);
+        assert_eq!(stats.records, [2, 1, 0]);
+        assert_eq!(stats.pairs, [1, 1, 0]);
+        assert_eq!(stats.nb_patterns_found(), 2);
+        assert_eq!(stats.position_histograms[0], [1, 0, 0, 0, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(stats.mean_hits_per_record(0), 1.5);
+        assert_eq!(stats.mean_hits_per_record(2), 0.0);
+        // Without reverse complements (-r), the strands are not counted
+        assert_eq!(stats.strand_hits(0), None);
+
+        let json = stats.to_json(&pattern_list, false);
+        assert!(json["AAC"]["forward_hits"].is_null());
+        assert!(json["AAC"]["reverse_complement_hits"].is_null());
+        assert!(json["AAC"]["pairs"].is_null());
+        assert!(stats.to_text(&pattern_list, false).contains("\n#AAC\t3\t2\t1.50\tNA\tNA\t1,0,0,0,0,1,0,0,0,1\n"));
+        assert!(stats.to_text(&pattern_list, true).contains("\n#AAC\t3\t2\t1\t1.50\t"));
+        assert_eq!(stats.occurrences_json(&pattern_list)["AAC"], 3);
+    }
+
+    #[test]
+    fn test_pattern_stats_strands() {
+        // AAC and TTT were given with -r; GTT is the reverse complement of
+        // AAC, and AAA (not found) of TTT
+        let pattern_list = vec!["AAA".to_string(), "AAC".to_string(), "GTT".to_string(), "TTT".to_string()];
+        let forward = PatternOrigin { forward: true, reverse_complement: false };
+        let rev_compl = PatternOrigin { forward: false, reverse_complement: true };
+        let mut stats = PatternStats::with_origins(&[rev_compl, forward, rev_compl, forward]);
+        stats.add_hit(&hit(1, 0, 0, 20));
+        stats.add_hit(&hit(1, 0, 19, 20));
+        stats.add_hit(&hit(2, 1, 5, 10));
+        stats.add_hit(&hit(0, 2, 1, 10));
+
+        assert_eq!(stats.strand_hits(0), Some((0, 1)));
+        assert_eq!(stats.strand_hits(1), Some((2, 0)));
+        assert_eq!(stats.strand_hits(2), Some((0, 1)));
+        assert_eq!(stats.strand_hits(3), Some((0, 0)));
+        // The hits of each row, and thus of each column, add up to the
+        // occurrences
+        for idx in 0..pattern_list.len() {
+            let (forward, rev_compl) = stats.strand_hits(idx).unwrap();
+            assert_eq!(forward + rev_compl, stats.occurrences[idx]);
+        }
+        let total = |counts: &[u32]| counts.iter().sum::<u32>();
+        assert_eq!(
+            total(&stats.forward_hits) + total(&stats.reverse_complement_hits),
+            total(&stats.occurrences)
+        );
+
+        let mut merged = PatternStats::new(pattern_list.len());
+        merged.merge(&stats);
+        assert_eq!(merged.strand_hits(1), Some((2, 0)));
+
+        let json = stats.to_json(&pattern_list, false);
+        assert_eq!(json["AAC"]["forward_hits"], 2);
+        assert_eq!(json["AAC"]["reverse_complement_hits"], 0);
+        assert_eq!(json["GTT"]["forward_hits"], 0);
+        assert_eq!(json["GTT"]["reverse_complement_hits"], 1);
+        assert!(stats.to_text(&pattern_list, false).contains("\n#AAC\t2\t1\t2.00\t2\t0\t"));
+        assert!(stats.to_text(&pattern_list, false).contains("\n#GTT\t1\t1\t1.00\t0\t1\t"));
+
+        // A palindrome is a given k-mer, so its hits are forward hits
+        let both = PatternOrigin { forward: true, reverse_complement: true };
+        let mut stats = PatternStats::with_origins(&[both]);
+        stats.add_hit(&hit(0, 0, 0, 4));
+        assert_eq!(stats.strand_hits(0), Some((1, 0)));
+    }
+
+    #[test]
+    fn test_record_search() {
+        let pattern_list = vec!["ACG".to_string(), "CGT".to_string()];
+        let matcher = build_matcher(&pattern_list, false, false, None).unwrap();
+        let search = RecordSearch {
+            matcher: matcher.as_ref(),
+            quality_filter: QualityFilter { min_base_qual: Some(20), min_mean_qual: None },
+            record_filter: RecordFilter { min_length: Some(4), ..Default::default() },
+            qual_offset: 0,
+        };
+        let mut stats = SearchStats::new(PatternStats::new(pattern_list.len()));
+        let mut hits = Vec::new();
+
+        assert_eq!(search.search(&mut stats, b"ACGTT", Some(&[30, 30, 30, 0, 30]), None, &mut hits), Some(0));
+        assert_eq!(hits, [(0, 0)]);
+        assert_eq!(search.search(&mut stats, b"ACG", None, None, &mut hits), None);
+        assert!(hits.is_empty());
+        assert_eq!(search.search(&mut stats, b"TTTT", None, None, &mut hits), Some(1));
+        assert_eq!(search.search(&mut stats, b"ACGACG", None, None, &mut hits), Some(2));
+        assert!(search.has_hit(b"ACGT", None));
+        assert!(!search.has_hit(b"ACGT", Some(&[0; 4])));
+
+        assert_eq!(stats.nb_records_tot, 3);
+        assert_eq!(stats.nb_bases, 15);
+        assert_eq!(stats.nb_hits_tot, 3);
+        assert_eq!(stats.nb_records_hit, 2);
+        assert_eq!(stats.nb_hits_rejected, 1);
+        assert_eq!(stats.nb_records_skipped, 1);
+        assert_eq!(stats.patterns.occurrences, [3, 0]);
+        assert_eq!(stats.patterns.records, [2, 0]);
+
+        let mut total = SearchStats::new(PatternStats::new(pattern_list.len()));
+        total.merge(&stats);
+        total.merge(&stats);
+        assert_eq!(total.nb_hits_tot, 6);
+        assert_eq!(total.patterns.records, [4, 0]);
+
+        let summary = stats.summary_json(&pattern_list, true, false, false);
+        assert_eq!(summary["number_of_records_searched"], 3);
+        assert_eq!(summary["number_of_hits_rejected_for_quality"], 1);
+        assert!(summary["number_of_records_skipped_by_filters"].is_null());
+        assert_eq!(summary["pattern_statistics"]["ACG"]["occurrences"], 3);
+    }
+
+    #[test]
+    fn test_find_hits_same_for_both_algorithms() {
+        let pattern_list = vec!["AA".to_string(), "AAA".to_string(), "ACG".to_string(), "CGT".to_string()];
+        let ac = build_matcher(&pattern_list, true, false, None).unwrap();
+        let bndmq = BNDMqMatcher::new(&pattern_list, Some(1)).unwrap();
+        let quality_filter = QualityFilter {
+            min_base_qual: Some(20),
+            min_mean_qual: None,
+        };
+        let seq = b"AAAACGTACGTAA";
+        let qual = b"IIIIIIIIII!II";
+
+        let mut ac_hits = Vec::new();
+        let mut bndmq_hits = Vec::new();
+        let ac_rejected = find_hits(ac.as_ref(), &quality_filter, seq, Some(qual), 33, &mut ac_hits);
+        let bndmq_rejected = find_hits(&bndmq, &quality_filter, seq, Some(qual), 33, &mut bndmq_hits);
+
+        assert_eq!(ac_hits, bndmq_hits);
+        assert_eq!(ac_rejected, bndmq_rejected);
+        assert_eq!(ac_hits, [(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (3, 2), (4, 3), (7, 2), (11, 0)]);
+        assert_eq!(ac_rejected, 1);
+    }
+}